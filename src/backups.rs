@@ -0,0 +1,342 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! Backups of HEAD and uncommitted changes to tracked files, made before the
+//! steps that could lose them, and `grit backups` to list and prune them.
+//!
+//! A backup is a ref named after when the plan was made and the branch that
+//! was checked out, like `refs/grit/backup/20261017T071203.250Z/main`. It
+//! points to a commit made by `git stash create` when there were uncommitted
+//! changes, to HEAD otherwise. `git stash create` leaves untracked files out,
+//! so they aren't backed up: none of the steps touch them anyway. Worktrees
+//! of a repository share backups.
+
+use std::fmt;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use jiff::Timestamp;
+use jiff::tz::TimeZone;
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use crate::config::RepoConfig;
+use crate::git::{self, GitInvocation};
+use crate::journal::{format_time, short};
+use crate::plan::{RepoOutcome, RepoReport};
+use crate::{display_path, output};
+
+/// Where backups live
+pub(crate) const BACKUP_REFS: &str = "refs/grit/backup/";
+
+/// The message of backup commits and of the reflog entries of backup refs
+pub(crate) const SNAPSHOT_MESSAGE: &str = "grit backup";
+
+/// How many backups each repository keeps, unless configured otherwise
+pub(crate) const DEFAULT_KEEP_BACKUPS: usize = 20;
+
+/// Sorts like the time it stands for, and fits in a ref name. Down to the
+/// millisecond, so that two runs in the same second don't pick the same ref
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// Reads refs named with `TIME_FORMAT`, and the ones named down to the
+/// second before it was
+const PARSE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.fZ";
+
+/// The ref to back up to, for a plan made at `time`
+pub(crate) fn ref_name(time: Timestamp, branch: &str) -> String {
+    format!("{BACKUP_REFS}{}/{branch}", time.strftime(TIME_FORMAT))
+}
+
+/// Splits a backup ref into when it was made and the branch it's for
+fn parse_ref(refname: &str) -> Option<(Timestamp, &str)> {
+    let (time, branch) = refname.strip_prefix(BACKUP_REFS)?.split_once('/')?;
+    let time = jiff::civil::DateTime::strptime(PARSE_TIME_FORMAT, time)
+        .and_then(|time| time.to_zoned(TimeZone::UTC))
+        .ok()?
+        .timestamp();
+    Some((time, branch))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Backup {
+    /// The repository, or the first configured worktree of it
+    pub(crate) path: Utf8PathBuf,
+    #[serde(rename = "ref")]
+    pub(crate) refname: String,
+    pub(crate) time: Timestamp,
+    /// The branch that was checked out
+    pub(crate) branch: String,
+    pub(crate) commit: String,
+    /// Whether there were uncommitted changes to save on top of HEAD, in
+    /// which case `git stash apply` brings them back
+    pub(crate) with_changes: bool,
+}
+
+/// Deletes a backup, only if its ref still points where it did when listed
+pub(crate) fn prune_command(path: &Utf8Path, backup: &Backup) -> GitInvocation {
+    GitInvocation::new(path, &["update-ref", "-d", &backup.refname, &backup.commit])
+}
+
+/// Lists the backups of the repository at `path`, newest first. Refs under
+/// `refs/grit/backup` that grit didn't name are left out, so they're never
+/// pruned.
+pub(crate) async fn list(path: &Utf8Path) -> eyre::Result<Vec<Backup>> {
+    let output = git::run_git_command_quiet(
+        path,
+        &[
+            "for-each-ref",
+            "--format=%(refname)%00%(objectname)%00%(contents:subject)",
+            BACKUP_REFS,
+        ],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+
+    let mut backups = Vec::new();
+    for line in output.stdout.lines() {
+        let fields: Vec<&str> = line.split('\0').collect();
+        let [refname, commit, subject] = fields[..] else {
+            panic!("Unexpected `git for-each-ref` output in {path}: {line:?}");
+        };
+        let Some((time, branch)) = parse_ref(refname) else {
+            continue;
+        };
+        backups.push(Backup {
+            path: path.to_owned(),
+            refname: refname.to_string(),
+            time,
+            branch: branch.to_string(),
+            commit: commit.to_string(),
+            with_changes: subject == format!("On {branch}: {SNAPSHOT_MESSAGE}"),
+        });
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.time));
+    Ok(backups)
+}
+
+pub(crate) struct BackupsPlan {
+    pub(crate) repos: Vec<RepoBackups>,
+    /// Repositories we can't list backups of, with the reason why
+    pub(crate) skipped: Vec<(Utf8PathBuf, String)>,
+    /// Whether the backups beyond `keep_backups` are to be deleted
+    pub(crate) prune: bool,
+}
+
+pub(crate) struct RepoBackups {
+    pub(crate) path: Utf8PathBuf,
+    /// Newest first
+    pub(crate) backups: Vec<Backup>,
+    /// How many of the newest backups to keep, from `keep_backups`
+    pub(crate) keep: usize,
+    /// Deletes the backups beyond `keep`, when pruning
+    pub(crate) commands: Vec<GitInvocation>,
+}
+
+impl BackupsPlan {
+    /// Lists the backups of every repository, once for all the worktrees of
+    /// a repository, and plans deleting the old ones if `prune` is set.
+    pub(crate) async fn new(repos: &[RepoConfig], prune: bool) -> eyre::Result<Self> {
+        let mut plan = Self {
+            repos: Vec::new(),
+            skipped: Vec::new(),
+            prune,
+        };
+        let mut common_dirs = Vec::new();
+
+        for repo in repos {
+            if !repo.path.exists() {
+                let reason = "it's not on this machine, `grit bootstrap` clones it".to_string();
+                plan.skipped.push((repo.path.clone(), reason));
+                continue;
+            }
+            let common_dir = git::run_git_command_quiet(
+                &repo.path,
+                &["rev-parse", "--path-format=absolute", "--git-common-dir"],
+                git::GitCommandBehavior::AllowNonZeroExitCode,
+            )
+            .await?;
+            if !common_dir.status.success() {
+                let reason = "it's not a git repository".to_string();
+                plan.skipped.push((repo.path.clone(), reason));
+                continue;
+            }
+            let common_dir = common_dir.stdout.trim().to_string();
+            if common_dirs.contains(&common_dir) {
+                continue;
+            }
+            common_dirs.push(common_dir);
+
+            let backups = list(&repo.path).await?;
+            let keep = repo.keep_backups();
+            let commands = if prune {
+                backups
+                    .iter()
+                    .skip(keep)
+                    .map(|backup| prune_command(&repo.path, backup))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            plan.repos.push(RepoBackups {
+                path: repo.path.clone(),
+                backups,
+                keep,
+                commands,
+            });
+        }
+
+        Ok(plan)
+    }
+
+    pub(crate) fn is_noop(&self) -> bool {
+        self.repos.iter().all(|repo| repo.commands.is_empty())
+    }
+
+    /// Every backup, repository by repository
+    pub(crate) fn backups(&self) -> Vec<&Backup> {
+        self.repos.iter().flat_map(|repo| &repo.backups).collect()
+    }
+
+    /// Deletes the old backups, one repository at a time: deleting refs is
+    /// quick. A failing repository doesn't stop the others, unless
+    /// `fail_fast` is set.
+    pub(crate) async fn execute(&self, fail_fast: bool) -> Vec<RepoReport> {
+        let mut reports = Vec::new();
+        let mut aborted = false;
+        for repo in self.repos.iter().filter(|repo| !repo.commands.is_empty()) {
+            if aborted {
+                let reason = "an earlier repository failed, and --fail-fast was passed".to_string();
+                reports.push(RepoReport::new(
+                    repo.path.clone(),
+                    RepoOutcome::Skipped { reason },
+                ));
+                continue;
+            }
+
+            let mut outcome = RepoOutcome::Succeeded;
+            for command in &repo.commands {
+                if let Err(error) = command.run_quiet().await {
+                    eprintln!(
+                        "{} Failed to prune the backups of {}: {:#}",
+                        "❌".red(),
+                        display_path(&repo.path).bright_cyan(),
+                        error
+                    );
+                    aborted = fail_fast;
+                    outcome = RepoOutcome::Failed {
+                        step: "prune".to_string(),
+                        error: output::strip_ansi(&format!("{error:#}")),
+                    };
+                    break;
+                }
+            }
+            reports.push(RepoReport::new(repo.path.clone(), outcome));
+        }
+        reports
+    }
+}
+
+impl fmt::Display for BackupsPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "\n{}{}",
+            "Backups".bright_cyan(),
+            if self.prune { " Plan:" } else { ":" }
+        )?;
+
+        for repo in self.repos.iter().filter(|repo| !repo.backups.is_empty()) {
+            writeln!(
+                f,
+                "💾 {} {} {}, keeping {}",
+                display_path(&repo.path).bright_cyan(),
+                repo.backups.len().to_string().bright_yellow(),
+                if repo.backups.len() == 1 {
+                    "backup"
+                } else {
+                    "backups"
+                },
+                repo.keep.to_string().bright_yellow()
+            )?;
+            for backup in &repo.backups {
+                write!(
+                    f,
+                    "  {}  {} {}",
+                    format_time(backup.time).bright_blue(),
+                    short(&backup.commit).yellow(),
+                    backup.refname.bright_green()
+                )?;
+                if backup.with_changes {
+                    write!(f, " {}", "with uncommitted changes".dimmed())?;
+                }
+                writeln!(f)?;
+            }
+
+            for command in &repo.commands {
+                writeln!(f, "  {}: {}", "Will execute".bright_blue(), command)?;
+            }
+            let old = repo.backups.len().saturating_sub(repo.keep);
+            if !self.prune && old > 0 {
+                writeln!(
+                    f,
+                    "  {} {} older than the newest {}, {} deletes them",
+                    "⚠️".yellow(),
+                    old.to_string().bright_yellow(),
+                    repo.keep.to_string().bright_yellow(),
+                    "grit backups --prune".bright_green()
+                )?;
+            }
+        }
+
+        for (path, reason) in &self.skipped {
+            writeln!(
+                f,
+                "{} Skipping {}: {}",
+                "⚠️".yellow(),
+                display_path(path).bright_cyan(),
+                reason.yellow()
+            )?;
+        }
+
+        if self.repos.iter().all(|repo| repo.backups.is_empty()) {
+            writeln!(f, "ℹ️ No backups yet")?;
+        } else if self.prune && self.is_noop() {
+            writeln!(f, "✅ Nothing to prune")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ref_names() {
+        let time: Timestamp = "2026-10-17T07:12:03.25Z".parse().unwrap();
+        let refname = ref_name(time, "feature/login");
+        assert_eq!(
+            refname,
+            "refs/grit/backup/20261017T071203.250Z/feature/login"
+        );
+        assert_eq!(parse_ref(&refname), Some((time, "feature/login")));
+
+        // Named before backups were named down to the millisecond
+        let time: Timestamp = "2026-10-17T07:12:03Z".parse().unwrap();
+        assert_eq!(
+            parse_ref("refs/grit/backup/20261017T071203Z/main"),
+            Some((time, "main"))
+        );
+
+        // Not named by grit: left alone
+        assert_eq!(parse_ref("refs/grit/backup/before-rebase"), None);
+        assert_eq!(parse_ref("refs/grit/backup/yesterday/main"), None);
+    }
+}
@@ -0,0 +1,273 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! `grit bootstrap`: clones the configured repositories that are missing on
+//! this machine, from the `url` recorded in the config.
+
+use std::cell::Cell;
+use std::fmt;
+
+use camino::Utf8PathBuf;
+use futures_util::StreamExt;
+use indicatif::ProgressBar;
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use crate::config::RepoConfig;
+use crate::git::GitInvocation;
+use crate::plan::{RepoOutcome, RepoReport};
+use crate::progress::{self, RepoProgress};
+use crate::{display_path, output};
+
+pub(crate) struct BootstrapPlan {
+    pub(crate) clones: Vec<PlannedClone>,
+    /// Repositories that aren't here but that we can't clone, with the reason
+    /// why
+    pub(crate) skipped: Vec<(Utf8PathBuf, String)>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct PlannedClone {
+    pub(crate) path: Utf8PathBuf,
+    /// Created before cloning, if it doesn't exist yet
+    #[serde(skip)]
+    pub(crate) parent: Utf8PathBuf,
+    pub(crate) command: GitInvocation,
+}
+
+impl BootstrapPlan {
+    /// Plans cloning the repositories whose path doesn't exist, or is an empty
+    /// directory, which `git clone` is happy to clone into.
+    pub(crate) fn new(repos: &[RepoConfig]) -> Self {
+        let mut clones = Vec::new();
+        let mut skipped = Vec::new();
+
+        for repo in repos {
+            if repo.path.exists() {
+                let is_empty_dir = repo
+                    .path
+                    .read_dir_utf8()
+                    .is_ok_and(|mut entries| entries.next().is_none());
+                if is_empty_dir {
+                    // Made ahead of time, say, and waiting to be cloned into
+                } else if repo.path.join(".git").exists() {
+                    continue;
+                } else {
+                    skipped.push((
+                        repo.path.clone(),
+                        "it's there, but it's not a git repository, and git only clones into an empty directory".to_string(),
+                    ));
+                    continue;
+                }
+            }
+            let Some(url) = &repo.url else {
+                skipped.push((
+                    repo.path.clone(),
+                    "no url is recorded for it in the config".to_string(),
+                ));
+                continue;
+            };
+            let Some(parent) = repo.path.parent().filter(|p| !p.as_str().is_empty()) else {
+                skipped.push((
+                    repo.path.clone(),
+                    "its path has no parent directory to clone into".to_string(),
+                ));
+                continue;
+            };
+
+            clones.push(PlannedClone {
+                path: repo.path.clone(),
+                parent: parent.to_owned(),
+                // Cloning into the absolute path from the parent directory, so the
+                // command reads the same no matter where it's pasted
+                command: GitInvocation::new(
+                    parent,
+                    &[
+                        "clone",
+                        "--origin",
+                        repo.remote_name(),
+                        url,
+                        repo.path.as_str(),
+                    ],
+                ),
+            });
+        }
+
+        Self { clones, skipped }
+    }
+
+    pub(crate) fn is_noop(&self) -> bool {
+        self.clones.is_empty()
+    }
+
+    /// Clones every missing repository, `jobs` at a time, with a live view of
+    /// how each one is doing. A failing clone doesn't stop the others, unless
+    /// `fail_fast` is set: then the clones that haven't started yet are
+    /// reported as skipped.
+    pub(crate) async fn execute(&self, fail_fast: bool, jobs: usize) -> Vec<RepoReport> {
+        let aborted = Cell::new(false);
+        let progress = RepoProgress::new(self.clones.iter().map(|clone| clone.path.as_path()));
+        let bars: Vec<_> = self
+            .clones
+            .iter()
+            .map(|clone| progress.add(&clone.path))
+            .collect();
+
+        let mut outcomes: Vec<_> =
+            futures_util::stream::iter(self.clones.iter().zip(bars).enumerate())
+                .map(|(index, (clone, bar))| {
+                    let aborted = &aborted;
+                    let progress = &progress;
+                    async move {
+                        if aborted.get() {
+                            progress::finish_skipped(&bar);
+                            let reason = "an earlier repository failed, and --fail-fast was passed"
+                                .to_string();
+                            return (index, RepoOutcome::Skipped { reason });
+                        }
+
+                        let outcome = match clone.execute(&bar).await {
+                            Ok(()) => {
+                                progress::finish_succeeded(&bar);
+                                RepoOutcome::Succeeded
+                            }
+                            Err(error) => {
+                                if fail_fast {
+                                    aborted.set(true);
+                                }
+                                progress::finish_failed(&bar, "clone");
+                                progress.suspend(|| {
+                                    eprintln!(
+                                        "{} Failed to clone {}: {:#}",
+                                        "❌".red(),
+                                        display_path(&clone.path).bright_cyan(),
+                                        error
+                                    )
+                                });
+                                RepoOutcome::Failed {
+                                    step: "clone".to_string(),
+                                    error: output::strip_ansi(&format!("{error:#}")),
+                                }
+                            }
+                        };
+                        (index, outcome)
+                    }
+                })
+                .buffer_unordered(jobs.max(1))
+                .collect()
+                .await;
+        outcomes.sort_by_key(|(index, _)| *index);
+
+        let mut reports: Vec<_> = outcomes
+            .into_iter()
+            .map(|(index, outcome)| RepoReport::new(self.clones[index].path.clone(), outcome))
+            .collect();
+        for (path, reason) in &self.skipped {
+            let outcome = RepoOutcome::Skipped {
+                reason: reason.clone(),
+            };
+            reports.push(RepoReport::new(path.clone(), outcome));
+        }
+
+        reports
+    }
+}
+
+impl PlannedClone {
+    async fn execute(&self, bar: &ProgressBar) -> eyre::Result<()> {
+        std::fs::create_dir_all(&self.parent)?;
+        progress::set_running(bar, "git clone");
+        self.command.run_quiet().await
+    }
+}
+
+impl fmt::Display for BootstrapPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\n{} Plan:", "Bootstrap".bright_cyan())?;
+
+        for clone in &self.clones {
+            writeln!(f, "📥 {}", display_path(&clone.path).bright_cyan())?;
+            if !clone.parent.exists() {
+                writeln!(
+                    f,
+                    "  {}: {}",
+                    "Will create".bright_blue(),
+                    clone.parent.bright_cyan()
+                )?;
+            }
+            writeln!(f, "  {}: {}", "Will execute".bright_blue(), clone.command)?;
+        }
+
+        for (path, reason) in &self.skipped {
+            writeln!(
+                f,
+                "{} Not cloning {}: {}",
+                "⚠️".yellow(),
+                display_path(path).bright_cyan(),
+                reason.yellow()
+            )?;
+        }
+
+        if self.clones.is_empty() && self.skipped.is_empty() {
+            writeln!(f, "✅ Every configured repository is already here")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_clones() -> eyre::Result<()> {
+        let root = Utf8PathBuf::try_from(std::env::temp_dir())?
+            .join(format!("grit-bootstrap-test-{}", fastrand::u64(..)));
+        std::fs::create_dir_all(root.join("here/.git"))?;
+        std::fs::create_dir_all(root.join("empty"))?;
+        std::fs::create_dir_all(root.join("taken"))?;
+        std::fs::write(root.join("taken/notes.md"), "# Notes\n")?;
+
+        let repo = |name: &str, url: Option<&str>| {
+            let mut repo = RepoConfig::from_path(root.join(name));
+            repo.url = url.map(str::to_string);
+            repo
+        };
+        let url = "git@example.com:repo.git";
+        let plan = BootstrapPlan::new(&[
+            repo("missing", Some(url)),
+            repo("no-url", None),
+            repo("here", Some(url)),
+            repo("empty", Some(url)),
+            repo("taken", Some(url)),
+        ]);
+        std::fs::remove_dir_all(&root)?;
+
+        let clones: Vec<_> = plan.clones.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(clones, [root.join("missing"), root.join("empty")]);
+        assert_eq!(plan.clones[0].parent, root);
+        assert_eq!(
+            plan.clones[0].command.args,
+            [
+                "clone",
+                "--origin",
+                "origin",
+                url,
+                root.join("missing").as_str()
+            ]
+        );
+
+        let skipped: Vec<_> = plan.skipped.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(skipped, [root.join("no-url"), root.join("taken")]);
+        assert!(plan.skipped[0].1.contains("no url"));
+        assert!(plan.skipped[1].1.contains("not a git repository"));
+        Ok(())
+    }
+}
@@ -0,0 +1,375 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! Branches other than the checked out one, listed in `branches`, and the
+//! remotes other than `remote` that branches get pushed to, listed in
+//! `push_remotes`.
+//!
+//! Branches that aren't checked out are fast-forwarded without touching the
+//! working tree, with `git fetch . <upstream>:<branch>`.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::Style;
+use serde::Serialize;
+
+use crate::config::RepoConfig;
+use crate::git;
+use crate::status::{count_ahead_behind, count_commits, join_state_parts};
+
+/// A branch from `branches` that isn't checked out
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct BranchStatus {
+    pub(crate) name: String,
+    /// Whether there's a local branch by that name
+    pub(crate) exists: bool,
+    /// The branch's upstream, like `origin/release`, `None` if it has none
+    pub(crate) upstream: Option<String>,
+    /// Where the upstream lives, to fast-forward from it and push to it
+    #[serde(skip)]
+    pub(crate) upstream_ref: Option<UpstreamRef>,
+    /// Number of local commits not yet on the upstream branch
+    pub(crate) ahead: usize,
+    /// Number of upstream commits not yet in the local branch
+    pub(crate) behind: usize,
+    /// The worktree that has the branch checked out, if another one does
+    pub(crate) checked_out_in: Option<Utf8PathBuf>,
+}
+
+/// The refs behind a branch's upstream
+#[derive(Debug, Clone)]
+pub(crate) struct UpstreamRef {
+    /// Like `refs/remotes/origin/release`
+    pub(crate) local_ref: String,
+    /// Like `origin`, or `.` when the upstream is a local branch
+    pub(crate) remote: String,
+    /// The branch's name on the remote, like `release`
+    pub(crate) remote_branch: String,
+}
+
+impl BranchStatus {
+    /// Whether the branch only lacks upstream commits, so updating it can't
+    /// lose anything
+    pub(crate) fn can_fast_forward(&self) -> bool {
+        self.upstream_ref.is_some()
+            && self.behind > 0
+            && self.ahead == 0
+            && self.checked_out_in.is_none()
+    }
+
+    /// Whether the upstream only lacks local commits, so pushing can't be
+    /// rejected
+    pub(crate) fn can_push(&self) -> bool {
+        self.upstream_ref
+            .as_ref()
+            .is_some_and(|upstream| upstream.remote != ".")
+            && self.ahead > 0
+            && self.behind == 0
+    }
+}
+
+/// A remote from `push_remotes`, with how each synced branch compares to it
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PushRemoteStatus {
+    pub(crate) remote: String,
+    /// Whether the repository has a remote by that name
+    pub(crate) exists: bool,
+    /// The checked out branch, then the existing ones from `branches`
+    pub(crate) branches: Vec<RemoteBranch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RemoteBranch {
+    pub(crate) name: String,
+    /// Number of local commits the remote doesn't have
+    pub(crate) ahead: usize,
+    /// Number of commits on the remote's branch that the local one doesn't
+    /// have: pushing would be rejected
+    pub(crate) behind: usize,
+}
+
+/// Gathers the state of the branches listed in `branches`, leaving out the
+/// checked out one.
+pub(crate) async fn gather_branches(
+    path: &Utf8Path,
+    config: &RepoConfig,
+    checked_out: Option<&str>,
+) -> eyre::Result<Vec<BranchStatus>> {
+    let mut branches = Vec::new();
+    for name in &config.branches {
+        if Some(name.as_str()) == checked_out {
+            continue;
+        }
+        branches.push(gather_branch(path, name).await?);
+    }
+    Ok(branches)
+}
+
+async fn gather_branch(path: &Utf8Path, name: &str) -> eyre::Result<BranchStatus> {
+    let refname = format!("refs/heads/{name}");
+    let output = git::run_git_command_quiet(
+        path,
+        &[
+            "for-each-ref",
+            "--format=%(refname)%00%(upstream)%00%(upstream:short)%00%(upstream:remotename)%00%(upstream:remoteref)%00%(worktreepath)",
+            &refname,
+        ],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+
+    let mut branch = BranchStatus {
+        name: name.to_string(),
+        exists: false,
+        upstream: None,
+        upstream_ref: None,
+        ahead: 0,
+        behind: 0,
+        checked_out_in: None,
+    };
+    // The pattern also matches branches under it, like `release/1.0`
+    let Some(line) = output
+        .stdout
+        .lines()
+        .find(|line| line.split('\0').next() == Some(refname.as_str()))
+    else {
+        return Ok(branch);
+    };
+    let fields: Vec<&str> = line.split('\0').collect();
+    let [_, upstream_ref, upstream, remote, remote_ref, worktree] = fields[..] else {
+        panic!("Unexpected `git for-each-ref` output in {path}: {line:?}");
+    };
+    branch.exists = true;
+    branch.checked_out_in = (!worktree.is_empty()).then(|| Utf8PathBuf::from(worktree));
+
+    // An upstream whose branch was deleted on the remote is as good as none
+    if !upstream_ref.is_empty() && ref_exists(path, upstream_ref).await? {
+        branch.upstream = Some(upstream.to_string());
+        branch.upstream_ref = Some(UpstreamRef {
+            local_ref: upstream_ref.to_string(),
+            remote: remote.to_string(),
+            remote_branch: remote_ref
+                .strip_prefix("refs/heads/")
+                .unwrap_or(remote_ref)
+                .to_string(),
+        });
+        (branch.ahead, branch.behind) = count_ahead_behind(path, upstream_ref, &refname).await?;
+    }
+    Ok(branch)
+}
+
+/// Gathers how the checked out branch and the existing `branches` compare to
+/// each remote listed in `push_remotes`, except the one we sync with.
+pub(crate) async fn gather_push_remotes(
+    path: &Utf8Path,
+    config: &RepoConfig,
+    checked_out: Option<&str>,
+    branches: &[BranchStatus],
+) -> eyre::Result<Vec<PushRemoteStatus>> {
+    let names: Vec<&str> = checked_out
+        .into_iter()
+        .chain(
+            branches
+                .iter()
+                .filter(|branch| branch.exists)
+                .map(|branch| branch.name.as_str()),
+        )
+        .collect();
+
+    let mut remotes = Vec::new();
+    for remote in &config.push_remotes {
+        if remote == config.remote_name() {
+            continue;
+        }
+        let url = git::run_git_command_quiet(
+            path,
+            &["remote", "get-url", remote],
+            git::GitCommandBehavior::AllowNonZeroExitCode,
+        )
+        .await?;
+        let mut push_remote = PushRemoteStatus {
+            remote: remote.clone(),
+            exists: url.status.success(),
+            branches: Vec::new(),
+        };
+        if push_remote.exists {
+            for name in &names {
+                let local = format!("refs/heads/{name}");
+                let remote_ref = format!("refs/remotes/{remote}/{name}");
+                let (ahead, behind) = if ref_exists(path, &remote_ref).await? {
+                    count_ahead_behind(path, &remote_ref, &local).await?
+                } else {
+                    // The remote doesn't have the branch yet
+                    let remotes = format!("--remotes={remote}");
+                    (count_commits(path, &[&local, "--not", &remotes]).await?, 0)
+                };
+                push_remote.branches.push(RemoteBranch {
+                    name: name.to_string(),
+                    ahead,
+                    behind,
+                });
+            }
+        }
+        remotes.push(push_remote);
+    }
+    Ok(remotes)
+}
+
+async fn ref_exists(path: &Utf8Path, refname: &str) -> eyre::Result<bool> {
+    let output = git::run_git_command_quiet(
+        path,
+        &["rev-parse", "--verify", "--quiet", refname],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    Ok(output.status.success())
+}
+
+/// Returns the colored state summary of a branch along with its visible
+/// width, like the status table does for repositories.
+pub(crate) fn describe_branch(branch: &BranchStatus) -> (String, usize) {
+    let mut parts = Vec::new();
+    if !branch.exists {
+        parts.push(("missing".to_string(), Style::new().bright_red()));
+    } else if branch.upstream.is_none() {
+        parts.push(("no upstream".to_string(), Style::new().yellow()));
+    }
+    if branch.ahead > 0 && branch.behind > 0 {
+        parts.push(("diverged".to_string(), Style::new().bright_red()));
+    }
+    if branch.ahead > 0 {
+        parts.push((format!("↑{}", branch.ahead), Style::new().bright_blue()));
+    }
+    if branch.behind > 0 {
+        parts.push((format!("↓{}", branch.behind), Style::new().bright_magenta()));
+    }
+    if parts.is_empty() {
+        parts.push(("clean".to_string(), Style::new().green()));
+    }
+    if let Some(worktree) = &branch.checked_out_in {
+        let text = format!("in {}", crate::display_path(worktree));
+        parts.push((text, Style::new().dimmed()));
+    }
+    join_state_parts(&parts)
+}
+
+/// Returns the colored state summary of a push remote along with its visible
+/// width: which branches it's missing commits of, or lacks altogether.
+pub(crate) fn describe_push_remote(remote: &PushRemoteStatus) -> (String, usize) {
+    let mut parts = Vec::new();
+    if !remote.exists {
+        parts.push(("missing".to_string(), Style::new().bright_red()));
+    }
+    for branch in &remote.branches {
+        if branch.behind > 0 {
+            let text = format!("{} diverged", branch.name);
+            parts.push((text, Style::new().bright_red()));
+        } else if branch.ahead > 0 {
+            let text = format!("{} ↑{}", branch.name, branch.ahead);
+            parts.push((text, Style::new().bright_blue()));
+        }
+    }
+    if parts.is_empty() {
+        parts.push(("up to date".to_string(), Style::new().green()));
+    }
+    join_state_parts(&parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn git(path: &Utf8Path, args: &[&str]) -> eyre::Result<()> {
+        let identity = ["-c", "user.name=grit", "-c", "user.email=grit@example.com"];
+        git::run_git_command_quiet(
+            path,
+            &[&identity[..], args].concat(),
+            git::GitCommandBehavior::AssertZeroExitCode,
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gather_branches() -> eyre::Result<()> {
+        let root = Utf8PathBuf::try_from(std::env::temp_dir())?
+            .join(format!("grit-branches-test-{}", fastrand::u64(..)));
+        let (upstream, local, mirror) = (
+            root.join("upstream"),
+            root.join("local"),
+            root.join("mirror"),
+        );
+        std::fs::create_dir_all(&upstream)?;
+        git(&upstream, &["init", "--quiet", "--initial-branch=main"]).await?;
+        git(
+            &upstream,
+            &["commit", "--quiet", "--allow-empty", "-m", "one"],
+        )
+        .await?;
+        git(&upstream, &["branch", "dev"]).await?;
+        git(
+            &root,
+            &["clone", "--quiet", upstream.as_str(), local.as_str()],
+        )
+        .await?;
+        git(&root, &["init", "--quiet", "--bare", mirror.as_str()]).await?;
+
+        git(&local, &["branch", "--track", "dev", "origin/dev"]).await?;
+        // Matched by the `refs/heads/release` pattern, but not `release`
+        git(&local, &["branch", "release/1.0"]).await?;
+        git(&local, &["remote", "add", "mirror", mirror.as_str()]).await?;
+        git(&local, &["push", "--quiet", "mirror", "main"]).await?;
+        git(&local, &["commit", "--quiet", "--allow-empty", "-m", "two"]).await?;
+        git(
+            &upstream,
+            &["commit", "--quiet", "--allow-empty", "-m", "three"],
+        )
+        .await?;
+        git(&upstream, &["checkout", "--quiet", "dev"]).await?;
+        git(
+            &upstream,
+            &["commit", "--quiet", "--allow-empty", "-m", "four"],
+        )
+        .await?;
+        git(&local, &["fetch", "--quiet", "origin"]).await?;
+
+        let mut config = RepoConfig::from_path(local.clone());
+        config.branches = ["main", "dev", "release"].map(String::from).to_vec();
+        config.push_remotes = ["origin", "mirror", "gone"].map(String::from).to_vec();
+        let branches = gather_branches(&local, &config, Some("main")).await?;
+        let remotes = gather_push_remotes(&local, &config, Some("main"), &branches).await?;
+        std::fs::remove_dir_all(&root)?;
+
+        let names: Vec<_> = branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["dev", "release"]);
+        let dev = &branches[0];
+        assert!(dev.exists);
+        assert_eq!(dev.upstream.as_deref(), Some("origin/dev"));
+        let upstream_ref = dev.upstream_ref.as_ref().unwrap();
+        assert_eq!(upstream_ref.local_ref, "refs/remotes/origin/dev");
+        assert_eq!(upstream_ref.remote, "origin");
+        assert_eq!(upstream_ref.remote_branch, "dev");
+        assert_eq!((dev.ahead, dev.behind), (0, 1));
+        assert!(dev.can_fast_forward());
+        assert!(!branches[1].exists);
+
+        // The remote we sync with isn't one of them
+        let names: Vec<_> = remotes.iter().map(|r| r.remote.as_str()).collect();
+        assert_eq!(names, ["mirror", "gone"]);
+        let counts: Vec<_> = remotes[0]
+            .branches
+            .iter()
+            .map(|b| (b.name.as_str(), b.ahead, b.behind))
+            .collect();
+        // The mirror has no `dev` branch, but has its only commit, through `main`
+        assert_eq!(counts, [("main", 1, 0), ("dev", 0, 0)]);
+        assert!(!remotes[1].exists);
+        assert!(remotes[1].branches.is_empty());
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use camino::Utf8PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// Program to keep git repositories in sync between computers
 #[derive(Parser, Debug)]
@@ -6,18 +7,161 @@ use clap::{Parser, Subcommand};
 pub(crate) struct Args {
     #[command(subcommand)]
     pub(crate) command: Commands,
+
+    /// Output format: `json` and `ndjson` write machine-readable records to stdout
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub(crate) format: OutputFormat,
+
+    /// Only consider repositories with this tag in the config (can be repeated)
+    #[arg(long, global = true)]
+    pub(crate) tag: Vec<String>,
 }
 
 /// Commands available for the sync operation
 #[derive(Subcommand, Debug)]
 pub(crate) enum Commands {
+    /// Show the state of all repositories without changing anything
+    Status,
     /// Pull latest changes for all repositories
-    Pull,
+    Pull {
+        #[command(flatten)]
+        consent: ConsentArgs,
+
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
     /// Push local changes for all repositories
-    Push,
+    Push {
+        #[command(flatten)]
+        consent: ConsentArgs,
+
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+    /// Commit, pull, then push every repository, with a single plan
+    Sync {
+        #[command(flatten)]
+        consent: ConsentArgs,
+
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+    /// Clone the configured repositories that are missing on this machine
+    Bootstrap {
+        #[command(flatten)]
+        consent: ConsentArgs,
+
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+    /// Add a repository to the config, recording its `origin` url
+    Add {
+        /// Path to the repository, the current directory if omitted
+        path: Option<Utf8PathBuf>,
+    },
+    /// Remove a repository from the config
+    Remove {
+        /// Path to the repository, as configured
+        path: Utf8PathBuf,
+    },
+    /// List the configured repositories and their options
+    List,
+    /// Show what the last runs of pull, push and sync changed on this machine
+    Log {
+        /// How many runs to show, most recent first
+        #[arg(short = 'n', long, default_value_t = 5)]
+        runs: usize,
+    },
+    /// Put back the branches the last pull, push or sync moved, as the journal
+    /// recorded them
+    Undo {
+        #[command(flatten)]
+        consent: ConsentArgs,
+
+        /// Stop at the first repository that fails, instead of carrying on with the others
+        #[arg(long)]
+        fail_fast: bool,
+    },
+    /// List the backups made before pulling or committing, or prune the old ones
+    Backups {
+        /// Delete the backups beyond `keep_backups`, oldest first
+        #[arg(long)]
+        prune: bool,
+
+        #[command(flatten)]
+        consent: ConsentArgs,
+
+        /// Stop at the first repository that fails, instead of carrying on with the others
+        #[arg(long)]
+        fail_fast: bool,
+    },
+    /// Find git repositories under a directory and add the new ones to the config
+    Scan {
+        /// Directory to search
+        dir: Utf8PathBuf,
+
+        /// How many directories deep to look
+        #[arg(long, default_value_t = 3)]
+        max_depth: usize,
+
+        #[command(flatten)]
+        consent: ConsentArgs,
+    },
 }
+
+/// How many repositories are worked on at once, unless `--jobs` says otherwise
+pub(crate) const DEFAULT_JOBS: u16 = 8;
+
+/// Options for commands that build a plan and then apply it
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub(crate) struct ConsentArgs {
+    /// Apply the plan without asking for consent, for cron jobs and CI
+    #[arg(short, long)]
+    pub(crate) yes: bool,
+
+    /// Show the plan, then exit without applying it
+    #[arg(long, conflicts_with = "yes")]
+    pub(crate) dry_run: bool,
+}
+
+/// Options for commands that work on many repositories at once
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub(crate) struct ExecArgs {
+    /// Stop at the first repository that fails, instead of carrying on with the others
+    #[arg(long)]
+    pub(crate) fail_fast: bool,
+
+    /// How many repositories to look at and work on at once. Steps that need
+    /// the terminal, like writing a commit message, always run one repository
+    /// at a time.
+    #[arg(short, long, default_value_t = DEFAULT_JOBS, value_parser = clap::value_parser!(u16).range(1..))]
+    pub(crate) jobs: u16,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum SyncMode {
     Pull,
     Push,
+    /// Commit local changes, pull, then push
+    Sync,
+}
+
+impl SyncMode {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SyncMode::Pull => "pull",
+            SyncMode::Push => "push",
+            SyncMode::Sync => "sync",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Colored, human-readable output on stderr
+    Text,
+    /// A single JSON document on stdout
+    Json,
+    /// One JSON object per line on stdout, written as soon as it's known
+    Ndjson,
 }
@@ -0,0 +1,100 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! Commit message templates, for `commit_mode = "auto"`.
+
+use owo_colors::OwoColorize;
+
+/// The template used when a repository commits automatically without saying
+/// what the message should be.
+pub(crate) const DEFAULT_COMMIT_MESSAGE: &str = "sync from {hostname} at {date}: {n} files";
+
+/// The placeholders a template may use: the name of this machine, today's
+/// date, and how many files changed
+const PLACEHOLDERS: &[&str] = &["hostname", "date", "n"];
+
+/// What the placeholders of a template are replaced with
+pub(crate) struct TemplateValues {
+    pub(crate) hostname: String,
+    pub(crate) date: String,
+    pub(crate) files: usize,
+}
+
+impl TemplateValues {
+    /// Values for a commit made on this machine, today.
+    pub(crate) fn current(files: usize) -> Self {
+        Self {
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            date: jiff::Zoned::now().strftime("%Y-%m-%d").to_string(),
+            files,
+        }
+    }
+}
+
+/// Replaces every `{placeholder}` in the template with its value.
+///
+/// Unknown placeholders and unclosed braces are errors, so that typos show up
+/// when reading the config rather than in the commit history.
+pub(crate) fn expand_template(template: &str, values: &TemplateValues) -> eyre::Result<String> {
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            return Err(eyre::eyre!(
+                "Unclosed {} in commit message template {}",
+                "{".red(),
+                template.bright_yellow()
+            ));
+        };
+        let name = &rest[start + 1..start + len];
+        match name {
+            "hostname" => message.push_str(&values.hostname),
+            "date" => message.push_str(&values.date),
+            "n" => message.push_str(&values.files.to_string()),
+            _ => {
+                let known: Vec<String> = PLACEHOLDERS
+                    .iter()
+                    .map(|name| format!("{{{name}}}"))
+                    .collect();
+                return Err(eyre::eyre!(
+                    "Unknown placeholder {} in commit message template {}, expected one of {}",
+                    format!("{{{name}}}").red(),
+                    template.bright_yellow(),
+                    known.join(", ")
+                ));
+            }
+        }
+        rest = &rest[start + len + 1..];
+    }
+    message.push_str(rest);
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() -> eyre::Result<()> {
+        let values = TemplateValues {
+            hostname: "laptop".to_string(),
+            date: "2025-03-14".to_string(),
+            files: 3,
+        };
+        assert_eq!(
+            expand_template(DEFAULT_COMMIT_MESSAGE, &values)?,
+            "sync from laptop at 2025-03-14: 3 files"
+        );
+        assert_eq!(expand_template("Sync notes", &values)?, "Sync notes");
+        assert!(expand_template("sync {host}", &values).is_err());
+        assert!(expand_template("sync {date", &values).is_err());
+        Ok(())
+    }
+}
@@ -10,84 +10,625 @@
 use camino::{Utf8Path, Utf8PathBuf};
 use eyre::WrapErr;
 use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::io::{self, Write};
-use std::process::Command;
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, value};
+
+use crate::guard::FileSize;
 
 /// Returns the path to the grit configuration file.
 pub fn get_config_path() -> String {
+    shellexpand::tilde("~/.config/grit/config.toml").to_string()
+}
+
+/// Returns the path to the legacy, line-based grit configuration file.
+pub fn get_legacy_config_path() -> String {
     shellexpand::tilde("~/.config/grit.conf").to_string()
 }
 
-/// Reads and parses the repositories from the given configuration file path.
+/// The parsed configuration, whichever format it came from
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    pub(crate) repos: Vec<RepoConfig>,
+    /// Globs or directories to search for repositories, with `~` expanded
+    pub(crate) include: Vec<String>,
+    /// Globs of repository paths to leave out, with `~` expanded
+    pub(crate) exclude: Vec<String>,
+    /// The pull strategy of repositories that don't pick their own
+    pub(crate) pull_strategy: Option<PullStrategy>,
+    /// What to do with diverged repositories that don't say otherwise
+    pub(crate) on_diverged: Option<OnDiverged>,
+    /// What to do with uncommitted changes when pulling, for repositories
+    /// that don't say otherwise
+    pub(crate) on_dirty: Option<OnDirty>,
+    /// How repositories that don't say otherwise write commit messages
+    pub(crate) commit_mode: Option<CommitMode>,
+    /// The `auto` commit message template of repositories that don't pick
+    /// their own
+    pub(crate) commit_message: Option<String>,
+    /// How repositories that don't say otherwise pick the files to stage
+    pub(crate) staging: Option<Staging>,
+    /// Globs of files never to stage, in every repository
+    pub(crate) stage_deny: Vec<String>,
+    /// Files bigger than this are flagged, in repositories that don't pick
+    /// their own limit
+    pub(crate) max_file_size: Option<FileSize>,
+    /// Globs of files that may be committed even when flagged, in every
+    /// repository
+    pub(crate) guard_allow: Vec<String>,
+    /// Whether repositories that don't say otherwise report their submodules
+    pub(crate) submodules: Option<bool>,
+    /// How many backups repositories that don't say otherwise keep
+    pub(crate) keep_backups: Option<usize>,
+}
+
+impl Config {
+    /// Adds every repository found through the `include` patterns, drops the
+    /// repositories matching an `exclude` pattern, then applies the global
+    /// options to the repositories that don't set their own.
+    ///
+    /// `auto_commit_message` is shorthand for `commit_mode = "auto"` with that
+    /// message, so it's turned into those. Global `stage_deny` and
+    /// `guard_allow` patterns are added to each repository's own. Commit
+    /// message templates and file patterns are checked here, so a typo fails
+    /// early rather than at commit time.
+    ///
+    /// Included directories are searched like `grit scan` does: the ones a
+    /// trailing `/**` stands for are searched all the way down, the others
+    /// only count if they're repositories themselves. Explicit entries win
+    /// over included ones, so their options stick.
+    fn resolve(mut self) -> eyre::Result<Self> {
+        for pattern in &self.include {
+            let max_depth = if pattern.ends_with("/**") {
+                usize::MAX
+            } else {
+                0
+            };
+            for dir in expand_include(pattern)? {
+                for path in crate::scan::find_repos(&dir, max_depth)? {
+                    if !self.repos.iter().any(|repo| repo.path == path) {
+                        self.repos.push(RepoConfig::from_path(path));
+                    }
+                }
+            }
+        }
+
+        let exclude = self
+            .exclude
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .wrap_err_with(|| format!("Invalid exclude pattern {}", pattern.red()))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        self.repos.retain(|repo| {
+            !exclude
+                .iter()
+                .any(|pattern| pattern.matches_path_with(repo.path.as_std_path(), options))
+        });
+
+        let example = crate::commit::TemplateValues {
+            hostname: String::new(),
+            date: String::new(),
+            files: 0,
+        };
+        for repo in &mut self.repos {
+            if let Some(message) = repo.auto_commit_message.take() {
+                repo.commit_mode.get_or_insert(CommitMode::Auto);
+                repo.commit_message.get_or_insert(message);
+            }
+            repo.pull_strategy = repo.pull_strategy.or(self.pull_strategy);
+            repo.on_diverged = repo.on_diverged.or(self.on_diverged);
+            repo.on_dirty = repo.on_dirty.or(self.on_dirty);
+            repo.commit_mode = repo.commit_mode.or(self.commit_mode);
+            if repo.commit_message.is_none() {
+                repo.commit_message = self.commit_message.clone();
+            }
+            repo.staging = repo.staging.or(self.staging);
+            repo.stage_deny
+                .splice(0..0, self.stage_deny.iter().cloned());
+            repo.max_file_size = repo.max_file_size.or(self.max_file_size);
+            repo.guard_allow
+                .splice(0..0, self.guard_allow.iter().cloned());
+            repo.submodules = repo.submodules.or(self.submodules);
+            repo.keep_backups = repo.keep_backups.or(self.keep_backups);
+            for (key, patterns) in [
+                ("stage_deny", &repo.stage_deny),
+                ("guard_allow", &repo.guard_allow),
+            ] {
+                for pattern in patterns {
+                    glob::Pattern::new(pattern)
+                        .wrap_err_with(|| format!("Invalid {key} pattern {}", pattern.red()))?;
+                }
+            }
+            crate::commit::expand_template(repo.commit_message_template(), &example)
+                .wrap_err_with(|| {
+                    format!("Invalid commit message for {}", repo.path.bright_cyan())
+                })?;
+        }
+
+        Ok(self)
+    }
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Turns an `include` pattern into the directories to search. A trailing
+/// `/**` means "everything under", which searching takes care of. Entries
+/// the glob can't read are skipped with a warning.
+fn expand_include(pattern: &str) -> eyre::Result<Vec<Utf8PathBuf>> {
+    let base = pattern.strip_suffix("/**").unwrap_or(pattern);
+    if !is_glob(base) {
+        let dir = Utf8PathBuf::from(base);
+        if !dir.is_dir() {
+            eprintln!(
+                "{} Included directory {} doesn't exist, skipping it",
+                "⚠️".yellow(),
+                crate::display_path(&dir).bright_cyan()
+            );
+            return Ok(Vec::new());
+        }
+        return Ok(vec![dir]);
+    }
+
+    let mut dirs = Vec::new();
+    for entry in
+        glob::glob(base).wrap_err_with(|| format!("Invalid include pattern {}", pattern.red()))?
+    {
+        let path = match entry {
+            Ok(path) => Utf8PathBuf::try_from(path)?,
+            Err(e) => {
+                eprintln!(
+                    "{} Skipping {} while including {}: {}",
+                    "⚠️".yellow(),
+                    e.path().display().bright_cyan(),
+                    pattern.bright_cyan(),
+                    e.error().to_string().yellow()
+                );
+                continue;
+            }
+        };
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+    Ok(dirs)
+}
+
+/// A repository entry, with its per-repo options
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RepoConfig {
+    /// Where the repository lives, with `~` already expanded
+    pub(crate) path: Utf8PathBuf,
+    /// Name of the remote to sync with, `origin` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) remote: Option<String>,
+    /// URL to clone the repository from, when it's missing on this machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) url: Option<String>,
+    /// Only sync the repository when this branch is checked out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) branch: Option<String>,
+    /// How to integrate upstream changes, the global `pull_strategy` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pull_strategy: Option<PullStrategy>,
+    /// What to do when local and upstream commits have diverged, the global
+    /// `on_diverged` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) on_diverged: Option<OnDiverged>,
+    /// What to do with uncommitted changes when pulling, the global
+    /// `on_dirty` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) on_dirty: Option<OnDirty>,
+    /// Set to false to never push from this repository
+    #[serde(default = "default_push")]
+    pub(crate) push: bool,
+    /// Free-form labels, used to select repositories with `--tag`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<String>,
+    /// How to write commit messages, the global `commit_mode` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) commit_mode: Option<CommitMode>,
+    /// The message template for `commit_mode = "auto"`, the global
+    /// `commit_message` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) commit_message: Option<String>,
+    /// How to pick the files to stage, the global `staging` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) staging: Option<Staging>,
+    /// Globs of files never to stage, on top of the global `stage_deny`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) stage_deny: Vec<String>,
+    /// Files bigger than this are flagged, the global `max_file_size` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_file_size: Option<FileSize>,
+    /// Globs of files that may be committed even when flagged, on top of the
+    /// global `guard_allow`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) guard_allow: Vec<String>,
+    /// Other local branches to keep in sync with their upstreams, besides
+    /// the checked out one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) branches: Vec<String>,
+    /// Other remotes to push to as well, like a mirror or a fork
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) push_remotes: Vec<String>,
+    /// Whether to report the state of submodules, the global `submodules` if
+    /// unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) submodules: Option<bool>,
+    /// How many backups to keep, the global `keep_backups` if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) keep_backups: Option<usize>,
+    /// Shorthand for `commit_mode = "auto"` with this message, folded into
+    /// those when the config is read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) auto_commit_message: Option<String>,
+}
+
+fn default_push() -> bool {
+    true
+}
+
+impl RepoConfig {
+    /// An entry with nothing but a path, as the legacy format describes them.
+    pub(crate) fn from_path(path: Utf8PathBuf) -> Self {
+        Self {
+            path,
+            remote: None,
+            url: None,
+            branch: None,
+            pull_strategy: None,
+            on_diverged: None,
+            on_dirty: None,
+            push: default_push(),
+            tags: Vec::new(),
+            commit_mode: None,
+            commit_message: None,
+            staging: None,
+            stage_deny: Vec::new(),
+            max_file_size: None,
+            guard_allow: Vec::new(),
+            branches: Vec::new(),
+            push_remotes: Vec::new(),
+            submodules: None,
+            keep_backups: None,
+            auto_commit_message: None,
+        }
+    }
+
+    pub(crate) fn remote_name(&self) -> &str {
+        self.remote.as_deref().unwrap_or("origin")
+    }
+
+    /// The strategy to pull with, `ff-only` unless configured otherwise
+    pub(crate) fn pull_strategy(&self) -> PullStrategy {
+        self.pull_strategy.unwrap_or_default()
+    }
+
+    /// What to do with uncommitted changes when pulling, `skip` unless
+    /// configured otherwise
+    pub(crate) fn on_dirty(&self) -> OnDirty {
+        self.on_dirty.unwrap_or_default()
+    }
+
+    /// How to write commit messages, `editor` unless configured otherwise
+    pub(crate) fn commit_mode(&self) -> CommitMode {
+        self.commit_mode.unwrap_or_default()
+    }
+
+    /// How to pick the files to stage, `all` unless configured otherwise
+    pub(crate) fn staging(&self) -> Staging {
+        self.staging.unwrap_or_default()
+    }
+
+    /// Files bigger than this are flagged, 10MB unless configured otherwise
+    pub(crate) fn max_file_size(&self) -> FileSize {
+        self.max_file_size.unwrap_or(FileSize::DEFAULT_LIMIT)
+    }
+
+    /// Whether to report the state of submodules, off unless configured
+    /// otherwise
+    pub(crate) fn submodules(&self) -> bool {
+        self.submodules.unwrap_or(false)
+    }
+
+    /// How many backups to keep, 20 unless configured otherwise. With 0, no
+    /// backups are made at all.
+    pub(crate) fn keep_backups(&self) -> usize {
+        self.keep_backups
+            .unwrap_or(crate::backups::DEFAULT_KEEP_BACKUPS)
+    }
+
+    /// The message template for `auto` commits
+    pub(crate) fn commit_message_template(&self) -> &str {
+        self.commit_message
+            .as_deref()
+            .unwrap_or(crate::commit::DEFAULT_COMMIT_MESSAGE)
+    }
+
+    /// The strategy to reconcile diverged branches with, `None` if they
+    /// should be left alone.
+    ///
+    /// An explicit `on_diverged` wins. Otherwise, the pull strategy is used
+    /// if it can reconcile them at all, which `ff-only` can't.
+    pub(crate) fn diverged_pull_strategy(&self) -> Option<PullStrategy> {
+        match self.on_diverged {
+            Some(OnDiverged::Skip) => None,
+            Some(OnDiverged::Rebase) => Some(PullStrategy::Rebase),
+            Some(OnDiverged::Merge) => Some(PullStrategy::Merge),
+            None => Some(self.pull_strategy()).filter(|s| s.handles_divergence()),
+        }
+    }
+}
+
+/// How commit messages get written
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum CommitMode {
+    /// Review the staged diff, then write the message in `$EDITOR`
+    #[default]
+    Editor,
+    /// Type a one-line message at a prompt
+    Prompt,
+    /// Use the `commit_message` template, no questions asked
+    Auto,
+}
+
+impl CommitMode {
+    /// Whether committing needs a human at the terminal
+    pub(crate) fn needs_terminal(&self) -> bool {
+        !matches!(self, CommitMode::Auto)
+    }
+}
+
+impl fmt::Display for CommitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CommitMode::Editor => "editor",
+            CommitMode::Prompt => "prompt",
+            CommitMode::Auto => "auto",
+        })
+    }
+}
+
+/// Which changed files get staged before committing. Files matching a
+/// `stage_deny` pattern never are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Staging {
+    /// Every changed file, untracked ones included
+    #[default]
+    All,
+    /// The files picked from a list, before the plan is shown
+    Pick,
+}
+
+impl fmt::Display for Staging {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Staging::All => "all",
+            Staging::Pick => "pick",
+        })
+    }
+}
+
+/// How to resolve a branch that has both local and upstream commits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OnDiverged {
+    /// Leave the repository alone, with a warning
+    Skip,
+    /// `git pull --rebase`, then push
+    Rebase,
+    /// `git pull --no-rebase`, then push
+    Merge,
+}
+
+/// How to pull into a working tree with uncommitted changes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OnDirty {
+    /// Don't pull, with a warning
+    #[default]
+    Skip,
+    /// `git pull --autostash`: stash the changes, pull, then unstash them
+    Autostash,
+    /// Commit the changes first, like `grit push` would, then pull
+    Commit,
+}
+
+impl fmt::Display for OnDirty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OnDirty::Skip => "skip",
+            OnDirty::Autostash => "autostash",
+            OnDirty::Commit => "commit",
+        })
+    }
+}
+
+impl fmt::Display for OnDiverged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OnDiverged::Skip => "skip",
+            OnDiverged::Rebase => "rebase",
+            OnDiverged::Merge => "merge",
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum PullStrategy {
+    /// `git pull --ff-only`, the default: it never creates commits, and
+    /// refuses to do anything when the branches have diverged
+    #[default]
+    FfOnly,
+    /// `git pull --rebase`
+    Rebase,
+    /// `git pull --no-rebase`
+    Merge,
+    /// `git pull --rebase --autostash`
+    RebaseAutostash,
+}
+
+impl PullStrategy {
+    /// Whether pulling can reconcile local and upstream commits, rather than
+    /// only fast-forward
+    pub(crate) fn handles_divergence(&self) -> bool {
+        !matches!(self, PullStrategy::FfOnly)
+    }
+
+    /// Whether pulling stashes uncommitted changes and restores them afterwards
+    pub(crate) fn autostashes(&self) -> bool {
+        matches!(self, PullStrategy::RebaseAutostash)
+    }
+
+    /// Arguments passed to `git pull` for this strategy
+    pub(crate) fn pull_args(&self) -> &'static [&'static str] {
+        match self {
+            PullStrategy::FfOnly => &["--ff-only"],
+            PullStrategy::Rebase => &["--rebase"],
+            PullStrategy::Merge => &["--no-rebase"],
+            PullStrategy::RebaseAutostash => &["--rebase", "--autostash"],
+        }
+    }
+}
+
+impl fmt::Display for PullStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PullStrategy::FfOnly => "ff-only",
+            PullStrategy::Rebase => "rebase",
+            PullStrategy::Merge => "merge",
+            PullStrategy::RebaseAutostash => "rebase-autostash",
+        })
+    }
+}
+
+/// The TOML config file, as written on disk
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default, rename = "repo")]
+    repos: Vec<RepoConfig>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    pull_strategy: Option<PullStrategy>,
+    #[serde(default)]
+    on_diverged: Option<OnDiverged>,
+    #[serde(default)]
+    on_dirty: Option<OnDirty>,
+    #[serde(default)]
+    commit_mode: Option<CommitMode>,
+    #[serde(default)]
+    commit_message: Option<String>,
+    #[serde(default)]
+    staging: Option<Staging>,
+    #[serde(default)]
+    stage_deny: Vec<String>,
+    #[serde(default)]
+    max_file_size: Option<FileSize>,
+    #[serde(default)]
+    guard_allow: Vec<String>,
+    #[serde(default)]
+    submodules: Option<bool>,
+    #[serde(default)]
+    keep_backups: Option<usize>,
+}
+
+/// Reads and parses the configuration, from the TOML file if there's one,
+/// from the legacy line-based file otherwise.
 ///
 /// # Arguments
 ///
-/// * `config_path` - A string slice that holds the path to the configuration file
+/// * `config_path` - A string slice that holds the path to the TOML configuration file
+/// * `legacy_config_path` - A string slice that holds the path to the legacy configuration file
 ///
 /// # Returns
 ///
-/// A Result containing a vector of Utf8PathBuf representing the repository paths
-fn read_repos_from_config(config_path: &str) -> eyre::Result<Vec<Utf8PathBuf>> {
+/// A Result containing the parsed configuration
+fn read_config(config_path: &str, legacy_config_path: &str) -> eyre::Result<Config> {
     let config_file = Utf8PathBuf::from(config_path);
+    let legacy_config_file = Utf8PathBuf::from(legacy_config_path);
 
-    if !config_file.exists() {
-        eprintln!("Config file not found at {}", config_path.bright_cyan());
-        eprint!(
-            "Do you want to create a default config file? ({}/{}): ",
-            "yes".green(),
-            "no".red()
-        );
-        io::stdout().flush().wrap_err("Failed to flush stdout")?;
-
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .wrap_err("Failed to read input")?;
+    if config_file.exists() {
+        let content = std::fs::read_to_string(&config_file).wrap_err_with(|| {
+            format!(
+                "Failed to read config file at {}",
+                config_path.bright_cyan()
+            )
+        })?;
+        return parse_toml_config(&content)
+            .wrap_err_with(|| {
+                format!(
+                    "Failed to parse config file at {}",
+                    config_path.bright_cyan()
+                )
+            })?
+            .resolve();
+    }
 
-        if input.trim().to_lowercase() != "yes" {
-            eprintln!("Exiting without creating config file.");
-            std::process::exit(0);
-        }
+    if legacy_config_file.exists() {
+        let content = std::fs::read_to_string(&legacy_config_file).wrap_err_with(|| {
+            format!(
+                "Failed to read config file at {}",
+                legacy_config_path.bright_cyan()
+            )
+        })?;
+        return parse_config_content(&content)?.resolve();
+    }
 
-        create_default_config(&config_file)?;
-        eprintln!(
-            "Default config file created at {}",
+    if !crate::prompt::is_interactive() {
+        return Err(eyre::eyre!(
+            "Config file not found at {}, and stdin is not a terminal so grit can't offer to create one",
             config_path.bright_cyan()
-        );
-
-        eprint!("Enter your favorite text editor to open the config file: ");
-        io::stdout().flush().wrap_err("Failed to flush stdout")?;
-
-        let mut editor = String::new();
-        io::stdin()
-            .read_line(&mut editor)
-            .wrap_err("Failed to read input")?;
-        let editor = editor.trim();
+        ));
+    }
 
-        eprintln!(
-            "Opening config file with {}. Press Ctrl+C to quit now if you don't want to proceed.",
-            editor
-        );
+    eprintln!("Config file not found at {}", config_path.bright_cyan());
+    eprint!(
+        "Do you want to create a default config file? ({}/{}): ",
+        "yes".green(),
+        "no".red()
+    );
+    io::stdout().flush().wrap_err("Failed to flush stdout")?;
 
-        Command::new(editor)
-            .arg(&config_file)
-            .status()
-            .wrap_err("Failed to open editor")?;
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .wrap_err("Failed to read input")?;
 
-        eprintln!("Config file has been opened. The program will now exit. Please run the command again after editing the config file.");
+    if input.trim().to_lowercase() != "yes" {
+        eprintln!("Exiting without creating config file.");
         std::process::exit(0);
     }
 
-    let content = std::fs::read_to_string(&config_file).wrap_err_with(|| {
-        format!(
-            "Failed to read config file at {}",
-            config_path.bright_cyan()
-        )
-    })?;
-    parse_config_content(&content)
+    create_default_config(&config_file)?;
+    eprintln!(
+        "Default config file created at {}",
+        config_path.bright_cyan()
+    );
+    eprintln!(
+        "Add repositories with {}, or edit the config file by hand, then run this command again.",
+        "grit add <path>".bright_green()
+    );
+    std::process::exit(0);
 }
 
-/// Parses the content of the configuration file.
+/// Parses the content of the TOML configuration file.
 ///
 /// # Arguments
 ///
@@ -95,24 +636,82 @@ fn read_repos_from_config(config_path: &str) -> eyre::Result<Vec<Utf8PathBuf>> {
 ///
 /// # Returns
 ///
-/// A Result containing a vector of Utf8PathBuf representing the repository paths
-fn parse_config_content(content: &str) -> eyre::Result<Vec<Utf8PathBuf>> {
-    content
-        .lines()
-        .filter_map(|line| {
-            let trimmed = line.trim();
-            if trimmed.is_empty() {
-                return None;
-            }
-            let parts: Vec<&str> = trimmed.splitn(2, '#').collect();
-            let path = parts[0].trim();
-            if path.is_empty() {
-                None
-            } else {
-                Some(Ok(Utf8PathBuf::from(shellexpand::tilde(path).to_string())))
-            }
+/// A Result containing the parsed configuration, with `~` expanded in paths
+/// and patterns
+fn parse_toml_config(content: &str) -> eyre::Result<Config> {
+    let file: ConfigFile = toml::from_str(content)?;
+    let repos = file
+        .repos
+        .into_iter()
+        .map(|repo| RepoConfig {
+            path: Utf8PathBuf::from(shellexpand::tilde(repo.path.as_str()).to_string()),
+            ..repo
         })
-        .collect()
+        .collect();
+    let expand = |patterns: Vec<String>| {
+        patterns
+            .iter()
+            .map(|pattern| shellexpand::tilde(pattern).to_string())
+            .collect()
+    };
+    Ok(Config {
+        repos,
+        include: expand(file.include),
+        exclude: expand(file.exclude),
+        pull_strategy: file.pull_strategy,
+        on_diverged: file.on_diverged,
+        on_dirty: file.on_dirty,
+        commit_mode: file.commit_mode,
+        commit_message: file.commit_message,
+        staging: file.staging,
+        stage_deny: file.stage_deny,
+        max_file_size: file.max_file_size,
+        guard_allow: file.guard_allow,
+        submodules: file.submodules,
+        keep_backups: file.keep_backups,
+    })
+}
+
+/// Parses the content of the legacy configuration file.
+///
+/// # Arguments
+///
+/// * `content` - A string slice containing the configuration file content
+///
+/// # Returns
+///
+/// A Result containing the parsed configuration: one entry per path, while
+/// globs, `include <dir>` and `!<glob>` lines become include and exclude patterns
+fn parse_config_content(content: &str) -> eyre::Result<Config> {
+    let mut config = Config::default();
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let parts: Vec<&str> = trimmed.splitn(2, '#').collect();
+        let path = parts[0].trim();
+        if path.is_empty() {
+            continue;
+        }
+
+        if let Some(pattern) = path.strip_prefix('!') {
+            config
+                .exclude
+                .push(shellexpand::tilde(pattern.trim()).to_string());
+        } else if let Some(pattern) = path.strip_prefix("include ") {
+            config
+                .include
+                .push(shellexpand::tilde(pattern.trim()).to_string());
+        } else if is_glob(path) {
+            config.include.push(shellexpand::tilde(path).to_string());
+        } else {
+            config.repos.push(RepoConfig::from_path(Utf8PathBuf::from(
+                shellexpand::tilde(path).to_string(),
+            )));
+        }
+    }
+    Ok(config)
 }
 
 /// Creates a default configuration file at the specified path.
@@ -126,19 +725,256 @@ fn parse_config_content(content: &str) -> eyre::Result<Vec<Utf8PathBuf>> {
 /// A Result indicating success or failure of the file creation
 fn create_default_config(config_file: &Utf8Path) -> eyre::Result<()> {
     let example_config = r#"# Grit configuration file
-# List one repository path per line, e.g.:
-# /home/user/projects/repo1
-# /home/user/projects/repo2
-# ~/Documents/github/my-project
+# How to pull, unless a repository says otherwise:
+#
+# pull_strategy = "ff-only"           # or "rebase", "merge", "rebase-autostash"
+# on_diverged = "skip"                # or "rebase", "merge" to reconcile, then push
+# on_dirty = "skip"                   # or "autostash", "commit" to pull into uncommitted changes
+#
+# How to write commit messages, unless a repository says otherwise:
+#
+# commit_mode = "editor"              # or "prompt" for a one-line message, "auto" for no questions
+# commit_message = "sync from {hostname} at {date}: {n} files"  # the "auto" message
+#
+# Which files to stage before committing:
+#
+# staging = "all"                     # or "pick" to choose from a list
+# stage_deny = [".env", "*.swp"]      # never stage these, in any repository
+#
+# Repositories about to commit large files, secrets or binaries are left
+# alone, unless the files are allowed:
+#
+# max_file_size = "10MB"
+# guard_allow = ["*.png"]             # commit these even when flagged
+#
+# Also show whether submodules have changes or unpushed commits:
+#
+# submodules = false
+#
+# Before pulling or committing, HEAD and uncommitted changes are backed up
+# under refs/grit/backup. How many backups to keep per repository (0 for none):
+#
+# keep_backups = 20
+#
+# Pick up every repository under a directory, minus some:
+#
+# include = ["~/projects/*", "~/work/**"]
+# exclude = ["~/projects/archive-*"]
+#
+# Add one [[repo]] section per repository, e.g.:
+#
+# [[repo]]
+# path = "~/projects/repo1"
+#
+# [[repo]]
+# path = "~/Documents/notes"
+# remote = "origin"                   # remote to sync with
+# url = "git@github.com:me/notes.git" # where `grit bootstrap` clones it from
+# branch = "main"                     # skip the repo when another branch is checked out
+# pull_strategy = "rebase"            # overrides the global pull_strategy
+# push = false                        # never push from this repo
+# tags = ["notes"]                    # select with `grit --tag notes pull`
+# commit_mode = "auto"                # commit without asking, e.g. from cron
+# commit_message = "Sync notes"       # overrides the global commit_message
+# stage_deny = ["*.log"]              # never stage these either
+# branches = ["drafts"]               # also fast-forward or push these branches
+# push_remotes = ["backup"]           # push to these remotes too
+# keep_backups = 5                    # overrides the global keep_backups
 "#;
 
+    if let Some(parent) = config_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
     std::fs::write(config_file, example_config)?;
     Ok(())
 }
 
-pub(crate) fn read_repos_from_default_config() -> eyre::Result<Vec<Utf8PathBuf>> {
+pub(crate) fn read_default_config() -> eyre::Result<Config> {
+    read_config(&get_config_path(), &get_legacy_config_path())
+}
+
+/// Like [`read_default_config`], but an empty config if there's no config
+/// file yet, instead of offering to create one.
+pub(crate) fn read_config_if_exists() -> eyre::Result<Config> {
     let config_path = get_config_path();
-    read_repos_from_config(&config_path)
+    let legacy_config_path = get_legacy_config_path();
+    if !Utf8Path::new(&config_path).exists() && !Utf8Path::new(&legacy_config_path).exists() {
+        return Ok(Config::default());
+    }
+    read_config(&config_path, &legacy_config_path)
+}
+
+/// Appends a repository to the config file, keeping its comments and ordering.
+///
+/// The TOML file is edited unless only the legacy file exists. If neither
+/// exists, a default TOML file is created first.
+///
+/// # Arguments
+///
+/// * `path` - The repository path, as it should be written (e.g. `~/projects/foo`)
+/// * `url` - The URL to clone it from, if known
+///
+/// # Returns
+///
+/// A Result containing the path of the config file that was edited
+pub(crate) fn add_repo_to_default_config(
+    path: &str,
+    url: Option<&str>,
+) -> eyre::Result<Utf8PathBuf> {
+    let config_file = Utf8PathBuf::from(get_config_path());
+    let legacy_config_file = Utf8PathBuf::from(get_legacy_config_path());
+
+    if !config_file.exists() && legacy_config_file.exists() {
+        let content = std::fs::read_to_string(&legacy_config_file)?;
+        std::fs::write(&legacy_config_file, add_repo_to_legacy(&content, path))?;
+        return Ok(legacy_config_file);
+    }
+
+    if !config_file.exists() {
+        create_default_config(&config_file)?;
+    }
+    let content = std::fs::read_to_string(&config_file)?;
+    let content = add_repo_to_toml(&content, path, url).wrap_err_with(|| {
+        format!(
+            "Failed to edit config file at {}",
+            config_file.bright_cyan()
+        )
+    })?;
+    std::fs::write(&config_file, content)?;
+    Ok(config_file)
+}
+
+/// Removes every entry for a repository from the config file, keeping the rest
+/// of the file as-is.
+///
+/// # Arguments
+///
+/// * `path` - The repository path, with `~` expanded
+///
+/// # Returns
+///
+/// A Result containing the path of the config file that was edited, or None
+/// if no entry matched
+pub(crate) fn remove_repo_from_default_config(
+    path: &Utf8Path,
+) -> eyre::Result<Option<Utf8PathBuf>> {
+    let config_file = Utf8PathBuf::from(get_config_path());
+    let legacy_config_file = Utf8PathBuf::from(get_legacy_config_path());
+
+    let (file, edited) = if config_file.exists() {
+        let content = std::fs::read_to_string(&config_file)?;
+        (config_file, remove_repo_from_toml(&content, path)?)
+    } else if legacy_config_file.exists() {
+        let content = std::fs::read_to_string(&legacy_config_file)?;
+        (legacy_config_file, remove_repo_from_legacy(&content, path))
+    } else {
+        return Ok(None);
+    };
+
+    match edited {
+        Some(content) => {
+            std::fs::write(&file, content)?;
+            Ok(Some(file))
+        }
+        None => Ok(None),
+    }
+}
+
+fn add_repo_to_toml(content: &str, path: &str, url: Option<&str>) -> eyre::Result<String> {
+    let mut doc: DocumentMut = content.parse()?;
+
+    let mut entry = Table::new();
+    entry.insert("path", value(path));
+    if let Some(url) = url {
+        entry.insert("url", value(url));
+    }
+
+    match doc.get_mut("repo") {
+        Some(Item::ArrayOfTables(repos)) => repos.push(entry),
+        Some(_) => return Err(eyre::eyre!("`repo` should be an array of tables")),
+        None => {
+            let mut repos = ArrayOfTables::new();
+            repos.push(entry);
+            doc.insert("repo", Item::ArrayOfTables(repos));
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+fn remove_repo_from_toml(content: &str, path: &Utf8Path) -> eyre::Result<Option<String>> {
+    let mut doc: DocumentMut = content.parse()?;
+    let Some(Item::ArrayOfTables(repos)) = doc.get_mut("repo") else {
+        return Ok(None);
+    };
+
+    let matches: Vec<usize> = repos
+        .iter()
+        .enumerate()
+        .filter(|(_, repo)| {
+            repo.get("path")
+                .and_then(|p| p.as_str())
+                .is_some_and(|p| shellexpand::tilde(p) == path.as_str())
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if matches.is_empty() {
+        return Ok(None);
+    }
+
+    let mut orphaned_header = None;
+    for &index in matches.iter().rev() {
+        let removed = repos.remove(index);
+        if index == 0 {
+            // Comments above the first entry are usually about the whole file
+            orphaned_header = removed
+                .decor()
+                .prefix()
+                .and_then(|prefix| prefix.as_str())
+                .map(str::to_string);
+        }
+    }
+
+    let header = orphaned_header.unwrap_or_default();
+    if let Some(first) = repos.get_mut(0) {
+        let prefix = first
+            .decor()
+            .prefix()
+            .and_then(|prefix| prefix.as_str())
+            .unwrap_or_default();
+        let prefix = format!("{header}{prefix}");
+        first.decor_mut().set_prefix(prefix);
+    } else {
+        doc.remove("repo");
+        let trailing = format!("{header}{}", doc.trailing().as_str().unwrap_or_default());
+        doc.set_trailing(trailing);
+    }
+    Ok(Some(doc.to_string()))
+}
+
+fn add_repo_to_legacy(content: &str, path: &str) -> String {
+    let mut content = content.to_string();
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(path);
+    content.push('\n');
+    content
+}
+
+fn remove_repo_from_legacy(content: &str, path: &Utf8Path) -> Option<String> {
+    let mut removed = false;
+    let mut kept = String::new();
+    for line in content.lines() {
+        let entry = line.split('#').next().unwrap_or_default().trim();
+        if !entry.is_empty() && shellexpand::tilde(entry) == path.as_str() {
+            removed = true;
+            continue;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    removed.then_some(kept)
 }
 
 #[cfg(test)]
@@ -154,22 +990,26 @@ mod tests {
 /path/to/repo2
 ~/path/to/repo3
 "#;
-        let repos = parse_config_content(content)?;
+        let repos = parse_config_content(content)?.repos;
 
         assert_eq!(repos.len(), 3);
-        assert_eq!(repos[0], Utf8PathBuf::from("/path/to/repo1"));
-        assert_eq!(repos[1], Utf8PathBuf::from("/path/to/repo2"));
+        assert_eq!(repos[0].path, Utf8PathBuf::from("/path/to/repo1"));
+        assert_eq!(repos[1].path, Utf8PathBuf::from("/path/to/repo2"));
         assert_eq!(
-            repos[2],
+            repos[2].path,
             Utf8PathBuf::from(shellexpand::tilde("~/path/to/repo3").to_string())
         );
+        assert_eq!(
+            repos[0],
+            RepoConfig::from_path(Utf8PathBuf::from("/path/to/repo1"))
+        );
         Ok(())
     }
 
     #[test]
     fn test_parse_config_content_empty_file() -> eyre::Result<()> {
         let content = "";
-        let repos = parse_config_content(content)?;
+        let repos = parse_config_content(content)?.repos;
         assert!(repos.is_empty());
         Ok(())
     }
@@ -181,8 +1021,294 @@ mod tests {
 
 # Another comment
 "#;
-        let repos = parse_config_content(content)?;
+        let repos = parse_config_content(content)?.repos;
         assert!(repos.is_empty());
         Ok(())
     }
+
+    #[test]
+    fn test_parse_config_content_with_patterns() -> eyre::Result<()> {
+        let content = r#"
+/path/to/repo1
+~/projects/*
+include /work/** # everything under /work
+!~/projects/archive-*
+"#;
+        let config = parse_config_content(content)?;
+        assert_eq!(
+            config.repos,
+            vec![RepoConfig::from_path(Utf8PathBuf::from("/path/to/repo1"))]
+        );
+        assert_eq!(
+            config.include,
+            vec![
+                shellexpand::tilde("~/projects/*").to_string(),
+                "/work/**".to_string()
+            ]
+        );
+        assert_eq!(
+            config.exclude,
+            vec![shellexpand::tilde("~/projects/archive-*").to_string()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve() -> eyre::Result<()> {
+        let root = Utf8PathBuf::try_from(std::env::temp_dir())?
+            .join(format!("grit-config-test-{}", fastrand::u64(..)));
+        for dir in [
+            "projects/app/.git",
+            "projects/archive-2019/.git",
+            "projects/not-a-repo",
+            "projects/group/nested/.git",
+            "work/team/service/.git",
+            "work/team/service/node_modules/dep/.git",
+        ] {
+            std::fs::create_dir_all(root.join(dir))?;
+        }
+
+        let mut app = RepoConfig::from_path(root.join("projects/app"));
+        app.push = false;
+        let config = Config {
+            repos: vec![app.clone()],
+            include: vec![format!("{root}/projects/*"), format!("{root}/work/**")],
+            exclude: vec![format!("{root}/projects/archive-*")],
+            pull_strategy: None,
+            on_diverged: None,
+            on_dirty: None,
+            commit_mode: None,
+            commit_message: None,
+            staging: None,
+            stage_deny: Vec::new(),
+            max_file_size: None,
+            guard_allow: Vec::new(),
+            submodules: None,
+            keep_backups: None,
+        }
+        .resolve();
+        std::fs::remove_dir_all(&root)?;
+
+        assert_eq!(
+            config?.repos,
+            vec![app, RepoConfig::from_path(root.join("work/team/service"))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_toml_config_with_all_options() -> eyre::Result<()> {
+        let content = r#"
+# Notes get synced without ceremony
+[[repo]]
+path = "~/Documents/notes"
+remote = "upstream"
+url = "git@github.com:me/notes.git"
+branch = "main"
+pull_strategy = "rebase-autostash"
+on_diverged = "skip"
+on_dirty = "autostash"
+push = false
+tags = ["notes", "personal"]
+branches = ["drafts"]
+push_remotes = ["backup"]
+keep_backups = 5
+auto_commit_message = "Sync notes"
+
+[[repo]]
+path = "/path/to/repo2"
+"#;
+        let repos = parse_toml_config(content)?.repos;
+
+        assert_eq!(repos.len(), 2);
+        assert_eq!(
+            repos[0],
+            RepoConfig {
+                path: Utf8PathBuf::from(shellexpand::tilde("~/Documents/notes").to_string()),
+                remote: Some("upstream".to_string()),
+                url: Some("git@github.com:me/notes.git".to_string()),
+                branch: Some("main".to_string()),
+                pull_strategy: Some(PullStrategy::RebaseAutostash),
+                on_diverged: Some(OnDiverged::Skip),
+                on_dirty: Some(OnDirty::Autostash),
+                push: false,
+                tags: vec!["notes".to_string(), "personal".to_string()],
+                commit_mode: None,
+                commit_message: None,
+                staging: None,
+                stage_deny: Vec::new(),
+                max_file_size: None,
+                guard_allow: Vec::new(),
+                branches: vec!["drafts".to_string()],
+                push_remotes: vec!["backup".to_string()],
+                submodules: None,
+                keep_backups: Some(5),
+                auto_commit_message: Some("Sync notes".to_string()),
+            }
+        );
+        assert_eq!(
+            repos[1],
+            RepoConfig::from_path(Utf8PathBuf::from("/path/to/repo2"))
+        );
+        assert_eq!(repos[1].remote_name(), "origin");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_toml_config_with_patterns() -> eyre::Result<()> {
+        let content = r#"
+include = ["~/projects/*"]
+exclude = ["/work/old-*"]
+
+[[repo]]
+path = "/path/to/repo"
+"#;
+        let config = parse_toml_config(content)?;
+        assert_eq!(config.repos.len(), 1);
+        assert_eq!(
+            config.include,
+            vec![shellexpand::tilde("~/projects/*").to_string()]
+        );
+        assert_eq!(config.exclude, vec!["/work/old-*".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_global_pull_strategy() -> eyre::Result<()> {
+        let content = r#"
+pull_strategy = "rebase"
+
+[[repo]]
+path = "/path/to/repo1"
+
+[[repo]]
+path = "/path/to/repo2"
+pull_strategy = "merge"
+"#;
+        let repos = parse_toml_config(content)?.resolve()?.repos;
+        assert_eq!(repos[0].pull_strategy(), PullStrategy::Rebase);
+        assert_eq!(repos[1].pull_strategy(), PullStrategy::Merge);
+
+        let repos = parse_config_content("/path/to/repo1\n")?.resolve()?.repos;
+        assert_eq!(repos[0].pull_strategy(), PullStrategy::FfOnly);
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_mode() -> eyre::Result<()> {
+        let content = r#"
+commit_mode = "prompt"
+
+[[repo]]
+path = "/path/to/repo1"
+
+[[repo]]
+path = "/path/to/repo2"
+auto_commit_message = "Sync notes"
+"#;
+        let repos = parse_toml_config(content)?.resolve()?.repos;
+        assert_eq!(repos[0].commit_mode(), CommitMode::Prompt);
+        assert_eq!(
+            repos[0].commit_message_template(),
+            crate::commit::DEFAULT_COMMIT_MESSAGE
+        );
+        assert_eq!(repos[1].commit_mode(), CommitMode::Auto);
+        assert_eq!(repos[1].commit_message_template(), "Sync notes");
+
+        let content = "[[repo]]\npath = \"/a\"\ncommit_message = \"sync {host}\"\n";
+        assert!(parse_toml_config(content)?.resolve().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diverged_pull_strategy() {
+        let mut repo = RepoConfig::from_path(Utf8PathBuf::from("/a"));
+        assert_eq!(repo.diverged_pull_strategy(), None);
+
+        repo.pull_strategy = Some(PullStrategy::RebaseAutostash);
+        assert_eq!(
+            repo.diverged_pull_strategy(),
+            Some(PullStrategy::RebaseAutostash)
+        );
+
+        repo.on_diverged = Some(OnDiverged::Skip);
+        assert_eq!(repo.diverged_pull_strategy(), None);
+
+        repo.pull_strategy = None;
+        repo.on_diverged = Some(OnDiverged::Merge);
+        assert_eq!(repo.diverged_pull_strategy(), Some(PullStrategy::Merge));
+    }
+
+    #[test]
+    fn test_parse_toml_config_empty_file() -> eyre::Result<()> {
+        let repos = parse_toml_config("# Nothing here yet\n")?.repos;
+        assert!(repos.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_toml_config_rejects_mistakes() {
+        // A typo in an option name shouldn't be silently ignored
+        assert!(parse_toml_config("[[repo]]\npath = \"/a\"\npul_strategy = \"rebase\"\n").is_err());
+        assert!(parse_toml_config("[[repo]]\npath = \"/a\"\npull_strategy = \"yolo\"\n").is_err());
+        assert!(parse_toml_config("[[repo]]\nbranch = \"main\"\n").is_err());
+    }
+
+    #[test]
+    fn test_add_and_remove_repo_in_toml_keeps_comments() -> eyre::Result<()> {
+        let content = r#"# My repos
+[[repo]]
+path = "~/a" # the important one
+tags = ["work"]
+"#;
+        let added = add_repo_to_toml(content, "~/b", Some("git@example.com:b.git"))?;
+        assert_eq!(
+            added,
+            r#"# My repos
+[[repo]]
+path = "~/a" # the important one
+tags = ["work"]
+
+[[repo]]
+path = "~/b"
+url = "git@example.com:b.git"
+"#
+        );
+
+        let a = Utf8PathBuf::from(shellexpand::tilde("~/a").to_string());
+        let removed = remove_repo_from_toml(&added, &a)?.expect("~/a should be removed");
+        let repos = parse_toml_config(&removed)?.repos;
+        assert_eq!(repos.len(), 1);
+        assert!(removed.starts_with("# My repos\n"));
+
+        assert!(remove_repo_from_toml(&removed, Utf8Path::new("/nope"))?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_repo_to_toml_without_entries() -> eyre::Result<()> {
+        let added = add_repo_to_toml("# Grit configuration file\n", "/path/to/repo", None)?;
+        let repos = parse_toml_config(&added)?.repos;
+        assert_eq!(
+            repos,
+            vec![RepoConfig::from_path(Utf8PathBuf::from("/path/to/repo"))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_and_remove_repo_in_legacy_config() -> eyre::Result<()> {
+        let content = "# Personal\n/path/to/repo1 # with comment";
+        let added = add_repo_to_legacy(content, "/path/to/repo2");
+        assert_eq!(
+            added,
+            "# Personal\n/path/to/repo1 # with comment\n/path/to/repo2\n"
+        );
+
+        let removed = remove_repo_from_legacy(&added, Utf8Path::new("/path/to/repo1"))
+            .expect("repo1 should be removed");
+        assert_eq!(removed, "# Personal\n/path/to/repo2\n");
+        assert!(remove_repo_from_legacy(&removed, Utf8Path::new("/nope")).is_none());
+        Ok(())
+    }
 }
@@ -7,11 +7,13 @@
 // 6. Better to panic if git output isn't as expected than to do harmful things
 // 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
 
+use std::fmt;
 use std::process::Stdio;
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use eyre::Context;
 use owo_colors::OwoColorize;
+use serde::Serialize;
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     process::Command,
@@ -44,6 +46,11 @@ pub(crate) async fn run_git_command(
 ) -> eyre::Result<GitCommandOutput> {
     let mut cmd = Command::new("git");
     cmd.current_dir(path).args(args);
+    if let GitCommandVerbosity::Quiet = verbosity {
+        // Quiet commands run several at a time, with nobody watching them:
+        // they fail rather than wait for a password nobody knows they want
+        cmd.stdin(Stdio::null()).env("GIT_TERMINAL_PROMPT", "0");
+    }
 
     if let GitCommandVerbosity::Verbose = verbosity {
         // Print the full git command
@@ -149,3 +156,269 @@ pub(crate) async fn run_git_command_quiet(
 ) -> eyre::Result<GitCommandOutput> {
     run_git_command(path, args, behavior, GitCommandVerbosity::Quiet).await
 }
+
+/// The commit a revision like `HEAD` points to, `None` if it doesn't exist,
+/// like `HEAD` before the first commit
+pub(crate) async fn resolve(path: &Utf8Path, rev: &str) -> eyre::Result<Option<String>> {
+    let output = run_git_command_quiet(
+        path,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{rev}^{{commit}}"),
+        ],
+        GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    Ok(output
+        .status
+        .success()
+        .then(|| output.stdout.trim().to_string()))
+}
+
+/// The uncommitted changes to tracked files, as `git status --porcelain`
+/// lines. Untracked files are left out.
+pub(crate) async fn tracked_changes(path: &Utf8Path) -> eyre::Result<Vec<String>> {
+    let output = run_git_command_quiet(
+        path,
+        &["status", "--porcelain", "--untracked-files=no"],
+        GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+    Ok(output.stdout.lines().map(str::to_string).collect())
+}
+
+/// A commit holding HEAD along with the uncommitted changes to tracked
+/// files, made with `git stash create`, which leaves the working tree and
+/// the stash list alone. HEAD itself when there are no changes, and `None`
+/// before the first commit.
+async fn snapshot_commit(path: &Utf8Path) -> eyre::Result<Option<String>> {
+    let Some(head) = resolve(path, "HEAD").await? else {
+        return Ok(None);
+    };
+    let output = run_git_command_quiet(
+        path,
+        &["stash", "create", crate::backups::SNAPSHOT_MESSAGE],
+        GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+    let stash = output.stdout.trim();
+    Ok(Some(if stash.is_empty() {
+        head
+    } else {
+        stash.to_string()
+    }))
+}
+
+/// The argument a `Snapshot` command's commit takes the place of
+pub(crate) const SNAPSHOT_ARG: &str = "<snapshot>";
+
+/// How a planned git command is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum InvocationMode {
+    /// Output is echoed as it comes, and a non-zero exit code is an error
+    Captured,
+    /// Like `Captured`, then waits for Enter so the output can be reviewed
+    Review,
+    /// Inherits the terminal, for commands that open an editor
+    Interactive,
+    /// Asks for a one-line commit message first, and passes it as the last
+    /// argument, after `-m`
+    Prompt,
+    /// Runs `git stash create` first, and passes the commit it makes in
+    /// place of the `SNAPSHOT_ARG` argument, or HEAD's when there's nothing
+    /// to stash. Does nothing before the first commit.
+    Snapshot,
+}
+
+/// A git command that a plan will run, exactly as it will be run.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct GitInvocation {
+    pub(crate) cwd: Utf8PathBuf,
+    /// Arguments passed to `git`, not including `git` itself
+    pub(crate) args: Vec<String>,
+    pub(crate) mode: InvocationMode,
+}
+
+impl GitInvocation {
+    pub(crate) fn new(cwd: &Utf8Path, args: &[&str]) -> Self {
+        Self::with_mode(cwd, args, InvocationMode::Captured)
+    }
+
+    pub(crate) fn review(cwd: &Utf8Path, args: &[&str]) -> Self {
+        Self::with_mode(cwd, args, InvocationMode::Review)
+    }
+
+    pub(crate) fn interactive(cwd: &Utf8Path, args: &[&str]) -> Self {
+        Self::with_mode(cwd, args, InvocationMode::Interactive)
+    }
+
+    pub(crate) fn prompt(cwd: &Utf8Path, args: &[&str]) -> Self {
+        Self::with_mode(cwd, args, InvocationMode::Prompt)
+    }
+
+    pub(crate) fn snapshot(cwd: &Utf8Path, args: &[&str]) -> Self {
+        Self::with_mode(cwd, args, InvocationMode::Snapshot)
+    }
+
+    fn with_mode(cwd: &Utf8Path, args: &[&str], mode: InvocationMode) -> Self {
+        Self {
+            cwd: cwd.to_owned(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            mode,
+        }
+    }
+
+    /// Whether the command needs the terminal: it waits for input, asks for a
+    /// message or opens an editor. Those can't run alongside anything else.
+    pub(crate) fn needs_terminal(&self) -> bool {
+        !matches!(
+            self.mode,
+            InvocationMode::Captured | InvocationMode::Snapshot
+        )
+    }
+
+    /// Runs a captured command without echoing anything, for when several
+    /// repositories are worked on at once. Its output only shows up in the
+    /// error, if it fails.
+    pub(crate) async fn run_quiet(&self) -> eyre::Result<()> {
+        assert!(
+            !self.needs_terminal(),
+            "{self} needs the terminal, it can't run quietly"
+        );
+        let mut args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let snapshot;
+        if let InvocationMode::Snapshot = self.mode {
+            let Some(commit) = snapshot_commit(&self.cwd).await? else {
+                return Ok(());
+            };
+            snapshot = commit;
+            replace_snapshot_arg(&mut args, &snapshot);
+        }
+        run_git_command_quiet(&self.cwd, &args, GitCommandBehavior::AssertZeroExitCode).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn run(&self) -> eyre::Result<()> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        match self.mode {
+            InvocationMode::Captured => {
+                assert_git_command(&self.cwd, &args).await?;
+            }
+            InvocationMode::Review => {
+                assert_git_command(&self.cwd, &args).await?;
+                eprintln!("Press Enter to continue...");
+                crate::prompt::read_line()?;
+            }
+            InvocationMode::Prompt => {
+                eprint!(
+                    "✏️ Commit message for {}: ",
+                    crate::display_path(&self.cwd).bright_cyan()
+                );
+                let message = crate::prompt::read_line()?;
+                if message.is_empty() {
+                    return Err(eyre::eyre!("No commit message given, not committing"));
+                }
+                let mut args = args;
+                args.push(&message);
+                assert_git_command(&self.cwd, &args).await?;
+            }
+            InvocationMode::Snapshot => {
+                let Some(commit) = snapshot_commit(&self.cwd).await? else {
+                    eprintln!(
+                        "{} Nothing to back up in {} before the first commit",
+                        "ℹ️".bright_blue(),
+                        crate::display_path(&self.cwd).bright_cyan()
+                    );
+                    return Ok(());
+                };
+                let mut args = args;
+                replace_snapshot_arg(&mut args, &commit);
+                assert_git_command(&self.cwd, &args).await?;
+            }
+            InvocationMode::Interactive => {
+                eprintln!("🚀 Running: {self}");
+                let status = Command::new("git")
+                    .current_dir(&self.cwd)
+                    .args(&args)
+                    .status()
+                    .await
+                    .wrap_err("Failed to spawn git command")?;
+
+                if !status.success() {
+                    return Err(eyre::eyre!(
+                        "Git command {} {} failed with exit code {} in directory {}",
+                        "git".bright_green(),
+                        args.join(" ").bright_cyan(),
+                        status.code().unwrap_or(-1).to_string().bright_red(),
+                        self.cwd.to_string().bright_blue(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn replace_snapshot_arg<'a>(args: &mut [&'a str], commit: &'a str) {
+    let arg = args
+        .iter_mut()
+        .find(|arg| **arg == SNAPSHOT_ARG)
+        .expect("snapshot commands have an argument for the snapshot");
+    *arg = commit;
+}
+
+impl fmt::Display for GitInvocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| {
+                if self.mode == InvocationMode::Snapshot && arg == SNAPSHOT_ARG {
+                    arg.bright_yellow().to_string()
+                } else {
+                    shell_quote(arg).bright_cyan().to_string()
+                }
+            })
+            .collect();
+        write!(f, "{} {}", "git".bright_green(), args.join(" "))?;
+        if self.mode == InvocationMode::Prompt {
+            write!(f, " {}", "<message>".bright_yellow())?;
+        }
+        write!(f, " {}", format!("(in {})", self.cwd).bright_blue())?;
+        match self.mode {
+            InvocationMode::Captured => {}
+            InvocationMode::Review => write!(f, " {}", "then waits for Enter".dimmed())?,
+            InvocationMode::Interactive => write!(f, " {}", "interactively".dimmed())?,
+            InvocationMode::Prompt => write!(f, " {}", "asks for the message first".dimmed())?,
+            InvocationMode::Snapshot => write!(f, " {}", "after `git stash create`".dimmed())?,
+        }
+        Ok(())
+    }
+}
+
+/// Quotes an argument so it can be pasted into a POSIX shell as-is.
+pub(crate) fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+^{}".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("push"), "push");
+        assert_eq!(shell_quote("@{u}..HEAD"), "@{u}..HEAD");
+        assert_eq!(shell_quote("sync from laptop"), "'sync from laptop'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
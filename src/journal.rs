@@ -0,0 +1,449 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! A journal of every executed plan on this machine, one JSON line per
+//! repository, so `grit log` can tell what a run changed.
+
+use std::io::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use jiff::Timestamp;
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+
+use crate::display_path;
+use crate::git;
+use crate::plan::{ActionStep, BranchMove, ExecutionPlan, RepoOutcome, RepoReport};
+use crate::undo::UndoPlan;
+
+/// How many commits `grit log` lists per range before summing up the rest
+const MAX_LISTED_COMMITS: usize = 10;
+
+/// Returns the path to the journal.
+pub(crate) fn get_journal_path() -> Utf8PathBuf {
+    Utf8PathBuf::from(shellexpand::tilde("~/.local/state/grit/journal.jsonl").to_string())
+}
+
+/// What a run did to one repository
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct JournalEntry {
+    /// When the run started, the same for every entry of a run
+    pub(crate) time: Timestamp,
+    /// The machine the run happened on
+    pub(crate) host: String,
+    /// The grit command, like `pull`
+    pub(crate) command: String,
+    pub(crate) path: Utf8PathBuf,
+    /// The checked out branch, `None` when HEAD was detached
+    pub(crate) branch: Option<String>,
+    /// The planned steps, whether they ran or not
+    pub(crate) steps: Vec<ActionStep>,
+    #[serde(flatten)]
+    pub(crate) outcome: RepoOutcome,
+    /// The commit HEAD pointed to before the steps ran
+    pub(crate) old_head: Option<String>,
+    /// The commit HEAD pointed to after the steps ran
+    pub(crate) new_head: Option<String>,
+    /// The branches from `branches` that the steps moved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) branches: Vec<BranchMove>,
+    /// The uncommitted changes to tracked files the steps left, as
+    /// `git status --porcelain` lines
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tracked_changes: Vec<String>,
+    /// For `grit undo`, when the run it took back started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) undoes: Option<Timestamp>,
+}
+
+impl JournalEntry {
+    /// Whether the run moved HEAD or one of the other branches
+    pub(crate) fn moved_refs(&self) -> bool {
+        self.old_head != self.new_head || !self.branches.is_empty()
+    }
+}
+
+/// Appends one entry per reported repository to the journal.
+pub(crate) fn record(
+    started: Timestamp,
+    plan: &ExecutionPlan,
+    reports: &[RepoReport],
+) -> eyre::Result<()> {
+    let host = hostname();
+    let mut entries = Vec::new();
+    for report in reports {
+        let repo_plan = plan
+            .repo_plans
+            .iter()
+            .find(|repo_plan| repo_plan.status.path == report.path)
+            .expect("every report comes from a repository of the plan");
+        let entry = JournalEntry {
+            time: started,
+            host: host.clone(),
+            command: plan.mode.name().to_string(),
+            path: report.path.clone(),
+            branch: repo_plan.status.branch.clone(),
+            steps: repo_plan.steps.iter().map(|step| step.action).collect(),
+            outcome: report.outcome.clone(),
+            old_head: report.old_head.clone(),
+            new_head: report.new_head.clone(),
+            branches: report.branches.clone(),
+            tracked_changes: report.tracked_changes.clone(),
+            undoes: None,
+        };
+        entries.push(entry);
+    }
+    write_entries(&entries)
+}
+
+/// Appends one entry per reported repository of a `grit undo` run, so the
+/// undone run isn't offered again.
+pub(crate) fn record_undo(
+    started: Timestamp,
+    plan: &UndoPlan,
+    reports: &[RepoReport],
+) -> eyre::Result<()> {
+    let Some(undone) = &plan.run else {
+        return Ok(());
+    };
+    let host = hostname();
+    let entries: Vec<_> = reports
+        .iter()
+        .map(|report| JournalEntry {
+            time: started,
+            host: host.clone(),
+            command: "undo".to_string(),
+            path: report.path.clone(),
+            branch: plan
+                .repos
+                .iter()
+                .find(|repo| repo.path == report.path)
+                .and_then(|repo| repo.branch.clone()),
+            steps: Vec::new(),
+            outcome: report.outcome.clone(),
+            old_head: report.old_head.clone(),
+            new_head: report.new_head.clone(),
+            branches: report.branches.clone(),
+            tracked_changes: report.tracked_changes.clone(),
+            undoes: Some(undone.time),
+        })
+        .collect();
+    write_entries(&entries)
+}
+
+fn hostname() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+fn write_entries(entries: &[JournalEntry]) -> eyre::Result<()> {
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+    append(&get_journal_path(), &lines)
+}
+
+fn append(path: &Utf8Path, lines: &str) -> eyre::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| eyre::eyre!("Failed to create {}: {e}", dir.red()))?;
+    }
+    // A single write per run, so concurrent runs don't interleave entries
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(|e| eyre::eyre!("Failed to write to {}: {e}", path.red()))
+}
+
+/// Reads every entry of the journal, oldest first. A missing journal has no
+/// entries, and lines that can't be parsed (say, cut short by a crash) are
+/// skipped with a warning.
+pub(crate) fn read_entries() -> eyre::Result<Vec<JournalEntry>> {
+    let path = get_journal_path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(eyre::eyre!("Failed to read {}: {e}", path.red())),
+    };
+    Ok(parse_entries(&content, &path))
+}
+
+fn parse_entries(content: &str, path: &Utf8Path) -> Vec<JournalEntry> {
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => eprintln!(
+                "{} Skipping line {} of {}: {e}",
+                "⚠️".yellow(),
+                (number + 1).to_string().bright_yellow(),
+                display_path(path).bright_cyan()
+            ),
+        }
+    }
+    entries
+}
+
+/// Groups entries by run, keeping their order. Entries of a run are written
+/// together, so they're next to each other.
+pub(crate) fn group_runs(entries: Vec<JournalEntry>) -> Vec<Vec<JournalEntry>> {
+    let mut runs: Vec<Vec<JournalEntry>> = Vec::new();
+    for entry in entries {
+        match runs.last_mut() {
+            Some(run) if run[0].time == entry.time && run[0].command == entry.command => {
+                run.push(entry)
+            }
+            _ => runs.push(vec![entry]),
+        }
+    }
+    runs
+}
+
+/// What's left to take back of the most recent run `grit undo` can take
+/// back: the entries of the run that moved HEAD or a branch somewhere, minus
+/// the ones an earlier undo already put back. Undo runs themselves are never
+/// taken back.
+pub(crate) fn last_undoable_run(runs: &[Vec<JournalEntry>]) -> Option<Vec<&JournalEntry>> {
+    // Only repositories whose undo went through are done with: the others
+    // are offered again, say once their working tree is clean
+    let undone: Vec<(Timestamp, &Utf8Path)> = runs
+        .iter()
+        .flatten()
+        .filter(|entry| matches!(entry.outcome, RepoOutcome::Succeeded))
+        .filter_map(|entry| Some((entry.undoes?, entry.path.as_path())))
+        .collect();
+    runs.iter()
+        .rev()
+        .filter(|run| run[0].undoes.is_none())
+        .map(|run| {
+            run.iter()
+                .filter(|entry| entry.moved_refs())
+                .filter(|entry| !undone.contains(&(entry.time, entry.path.as_path())))
+                .collect::<Vec<_>>()
+        })
+        .find(|left| !left.is_empty())
+}
+
+/// Like `2026-10-17 09:12`, in the local time zone
+pub(crate) fn format_time(time: Timestamp) -> String {
+    time.to_zoned(jiff::tz::TimeZone::system())
+        .strftime("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Prints each run with what it did to each repository, listing the commits
+/// that HEAD and the other branches moved over.
+pub(crate) async fn print_runs(runs: &[Vec<JournalEntry>]) {
+    if runs.is_empty() {
+        eprintln!(
+            "\n{} Nothing recorded yet in {}",
+            "ℹ️".bright_blue(),
+            display_path(&get_journal_path()).bright_cyan()
+        );
+        return;
+    }
+
+    for run in runs {
+        let first = &run[0];
+        eprint!(
+            "\n📜 {} on {}, {}",
+            first.command.bright_cyan(),
+            first.host.bright_yellow(),
+            format_time(first.time).bright_blue()
+        );
+        match first.undoes {
+            Some(undone) => eprintln!(
+                ", taking back the run of {}",
+                format_time(undone).bright_blue()
+            ),
+            None => eprintln!(),
+        }
+
+        let path_width = run
+            .iter()
+            .map(|entry| display_path(&entry.path).as_str().len())
+            .max()
+            .unwrap_or(0);
+        for entry in run {
+            let path = format!("{:path_width$}", display_path(&entry.path))
+                .bright_cyan()
+                .to_string();
+            match &entry.outcome {
+                RepoOutcome::Succeeded => {
+                    eprintln!(
+                        "  {path}  {} {}",
+                        "✅".green(),
+                        describe_move(&entry.old_head, &entry.new_head)
+                    )
+                }
+                RepoOutcome::Failed { step, error } => {
+                    let first_line = error.lines().next().unwrap_or_default();
+                    eprintln!(
+                        "  {path}  {} {} {}: {}",
+                        "❌".red(),
+                        "failed to".bright_red(),
+                        step.bright_yellow(),
+                        first_line.red()
+                    );
+                }
+                RepoOutcome::Skipped { reason } => {
+                    eprintln!(
+                        "  {path}  {} {}: {}",
+                        "⏭️".yellow(),
+                        "skipped".yellow(),
+                        reason
+                    );
+                }
+            }
+
+            let indent = " ".repeat(path_width + 4);
+            if matches!(entry.outcome, RepoOutcome::Failed { .. })
+                && entry.old_head != entry.new_head
+            {
+                eprintln!(
+                    "{indent}{}",
+                    describe_move(&entry.old_head, &entry.new_head)
+                );
+            }
+            print_commits(&entry.path, &entry.old_head, &entry.new_head, &indent).await;
+            for branch in &entry.branches {
+                eprintln!(
+                    "{indent}{} {}: {}",
+                    "⎇".dimmed(),
+                    branch.branch.green(),
+                    describe_move(&branch.old, &branch.new)
+                );
+                print_commits(&entry.path, &branch.old, &branch.new, &indent).await;
+            }
+        }
+    }
+}
+
+/// Like `1a2b3c4..5d6e7f8`, or says nothing changed
+fn describe_move(old: &Option<String>, new: &Option<String>) -> String {
+    match (old, new) {
+        (Some(old), Some(new)) if old != new => format!("{}..{}", short(old), short(new))
+            .bright_blue()
+            .to_string(),
+        (None, Some(new)) => format!("now at {}", short(new).bright_blue()),
+        (Some(_), None) => "the branch is gone".yellow().to_string(),
+        _ => "no new commits".dimmed().to_string(),
+    }
+}
+
+pub(crate) fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+/// Lists the commits in `old..new`, like `git log --oneline` would
+async fn print_commits(path: &Utf8Path, old: &Option<String>, new: &Option<String>, indent: &str) {
+    let (Some(old), Some(new)) = (old, new) else {
+        return;
+    };
+    if old == new {
+        return;
+    }
+    let range = format!("{old}..{new}");
+    let output = if path.exists() {
+        git::run_git_command_quiet(
+            path,
+            &["log", "--oneline", "--no-decorate", &range],
+            git::GitCommandBehavior::AllowNonZeroExitCode,
+        )
+        .await
+        .ok()
+    } else {
+        None
+    };
+    let Some(output) = output.filter(|output| output.status.success()) else {
+        eprintln!(
+            "{indent}{}",
+            "(the commits aren't in the repository anymore)".dimmed()
+        );
+        return;
+    };
+
+    let commits: Vec<&str> = output.stdout.lines().collect();
+    for commit in commits.iter().take(MAX_LISTED_COMMITS) {
+        let (sha, subject) = commit.split_once(' ').unwrap_or((commit, ""));
+        eprintln!("{indent}  {} {subject}", sha.yellow());
+    }
+    if commits.len() > MAX_LISTED_COMMITS {
+        eprintln!(
+            "{indent}  {}",
+            format!("… and {} more", commits.len() - MAX_LISTED_COMMITS).dimmed()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_group_entries() {
+        let content = r#"{"time":"2026-10-17T07:12:03Z","host":"laptop","command":"pull","path":"/home/me/a","branch":"main","steps":["pull"],"outcome":"succeeded","old_head":"1a2b3c4d","new_head":"5d6e7f8a"}
+{"time":"2026-10-17T07:12:03Z","host":"laptop","command":"pull","path":"/home/me/b","branch":"main","steps":[],"outcome":"skipped","reason":"there are uncommitted changes","old_head":null,"new_head":null}
+{"time":"2026-10-17T07:12:0
+{"time":"2026-10-17T08:00:00Z","host":"laptop","command":"push","path":"/home/me/a","branch":"main","steps":["push"],"outcome":"failed","step":"push","error":"rejected","old_head":"5d6e7f8a","new_head":"5d6e7f8a"}
+"#;
+        let entries = parse_entries(content, Utf8Path::new("journal.jsonl"));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].steps, [ActionStep::Pull]);
+        assert!(matches!(
+            &entries[1].outcome,
+            RepoOutcome::Skipped { reason } if reason == "there are uncommitted changes"
+        ));
+
+        let runs = group_runs(entries);
+        let sizes: Vec<_> = runs.iter().map(Vec::len).collect();
+        assert_eq!(sizes, [2, 1]);
+        assert_eq!(runs[1][0].command, "push");
+    }
+
+    #[test]
+    fn test_last_undoable_run() {
+        let content = r#"{"time":"2026-10-17T07:00:00Z","host":"laptop","command":"pull","path":"/home/me/a","branch":"main","steps":["pull"],"outcome":"succeeded","old_head":"1a2b3c4d","new_head":"5d6e7f8a"}
+{"time":"2026-10-17T08:00:00Z","host":"laptop","command":"pull","path":"/home/me/b","branch":"main","steps":["pull"],"outcome":"succeeded","old_head":"0a0b0c0d","new_head":"9e8f7a6b"}
+{"time":"2026-10-17T09:00:00Z","host":"laptop","command":"push","path":"/home/me/a","branch":"main","steps":["push"],"outcome":"succeeded","old_head":"5d6e7f8a","new_head":"5d6e7f8a"}
+"#;
+        let runs = group_runs(parse_entries(content, Utf8Path::new("journal.jsonl")));
+        // The push didn't move anything, so the last pull is what gets undone
+        let run = last_undoable_run(&runs).unwrap();
+        assert_eq!(run[0].path, "/home/me/b");
+
+        let undo = r#"{"time":"2026-10-17T10:00:00Z","host":"laptop","command":"undo","path":"/home/me/b","branch":"main","steps":[],"outcome":"succeeded","old_head":"9e8f7a6b","new_head":"0a0b0c0d","undoes":"2026-10-17T08:00:00Z"}
+"#;
+        let content = format!("{content}{undo}");
+        let runs = group_runs(parse_entries(&content, Utf8Path::new("journal.jsonl")));
+        // Neither the undo nor the run it took back are offered again
+        let run = last_undoable_run(&runs).unwrap();
+        assert_eq!(run[0].path, "/home/me/a");
+        assert_eq!(run[0].command, "pull");
+
+        let pull = r#"{"time":"2026-10-17T11:00:00Z","host":"laptop","command":"pull","path":"/home/me/a","branch":"main","steps":["pull"],"outcome":"succeeded","old_head":"5d6e7f8a","new_head":"7c7c7c7c"}
+{"time":"2026-10-17T11:00:00Z","host":"laptop","command":"pull","path":"/home/me/b","branch":"main","steps":["pull"],"outcome":"succeeded","old_head":"0a0b0c0d","new_head":"3d3d3d3d"}
+{"time":"2026-10-17T12:00:00Z","host":"laptop","command":"undo","path":"/home/me/a","branch":"main","steps":[],"outcome":"succeeded","old_head":"7c7c7c7c","new_head":"5d6e7f8a","undoes":"2026-10-17T11:00:00Z"}
+{"time":"2026-10-17T12:00:00Z","host":"laptop","command":"undo","path":"/home/me/b","branch":"main","steps":[],"outcome":"skipped","reason":"the working tree changed since the pull","old_head":null,"new_head":null,"undoes":"2026-10-17T11:00:00Z"}
+"#;
+        let content = format!("{content}{pull}");
+        let runs = group_runs(parse_entries(&content, Utf8Path::new("journal.jsonl")));
+        // The repository the undo skipped is still offered, on its own
+        let run = last_undoable_run(&runs).unwrap();
+        assert_eq!(run.len(), 1);
+        assert_eq!(run[0].path, "/home/me/b");
+        assert_eq!(run[0].command, "pull");
+    }
+}
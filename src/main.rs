@@ -7,22 +7,39 @@
 // 6. Better to panic if git output isn't as expected than to do harmful things
 // 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
 
+use backups::BackupsPlan;
+use bootstrap::BootstrapPlan;
 use camino::Utf8Path;
 use camino::Utf8PathBuf;
 use clap::Parser;
-use cli::{Args, Commands, SyncMode};
-use config::read_repos_from_default_config;
-use eyre::Context;
-use futures_util::StreamExt;
+use cli::{Args, Commands, ConsentArgs, ExecArgs, SyncMode};
+use config::{RepoConfig, read_default_config};
 use owo_colors::OwoColorize;
-use owo_colors::Style;
-use std::fmt;
-use std::io::{self, Write};
-
+use plan::{ExecutionPlan, RepoOutcome, RepoReport, print_results};
+use scan::ScanPlan;
+use status::{gather_repo_statuses, print_status_table};
+use undo::UndoPlan;
+
+mod backups;
+mod bootstrap;
+mod branches;
 mod cheer;
 mod cli;
+mod commit;
 mod config;
 mod git;
+mod guard;
+mod journal;
+mod manage;
+mod output;
+mod plan;
+mod progress;
+mod prompt;
+mod scan;
+mod staging;
+mod status;
+mod submodules;
+mod undo;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> eyre::Result<()> {
@@ -34,379 +51,304 @@ async fn real_main() -> eyre::Result<()> {
 
     let args = Args::parse();
 
-    match args.command {
-        Commands::Pull => sync_repos(SyncMode::Pull).await?,
-        Commands::Push => sync_repos(SyncMode::Push).await?,
+    match &args.command {
+        Commands::Status => show_status(&args).await?,
+        Commands::Pull { consent, exec } => {
+            sync_repos(SyncMode::Pull, consent, exec, &args).await?
+        }
+        Commands::Push { consent, exec } => {
+            sync_repos(SyncMode::Push, consent, exec, &args).await?
+        }
+        Commands::Sync { consent, exec } => {
+            sync_repos(SyncMode::Sync, consent, exec, &args).await?
+        }
+        Commands::Bootstrap { consent, exec } => bootstrap_repos(consent, exec, &args).await?,
+        Commands::Add { path } => manage::add_repo(path.as_deref()).await?,
+        Commands::Remove { path } => manage::remove_repo(path)?,
+        Commands::List => list_repos(&args)?,
+        Commands::Log { runs } => show_log(*runs, &args).await?,
+        Commands::Undo { consent, fail_fast } => undo_last_run(consent, *fail_fast).await?,
+        Commands::Backups {
+            prune,
+            consent,
+            fail_fast,
+        } => show_backups(*prune, consent, *fail_fast, &args).await?,
+        Commands::Scan {
+            dir,
+            max_depth,
+            consent,
+        } => scan_repos(dir, *max_depth, consent).await?,
     }
 
     Ok(())
 }
 
-async fn sync_repos(mode: SyncMode) -> eyre::Result<()> {
-    let repos = read_repos_from_default_config()?;
-    let mut repo_statuses = Vec::new();
-    let mut errors = Vec::new();
-
-    futures_util::stream::iter(repos.iter())
-        .map(|repo| async { get_repo_status(repo).await })
-        .buffer_unordered(8)
-        .for_each(|result| {
-            match result {
-                Ok(Some(status)) => repo_statuses.push(status),
-                Ok(None) => {}
-                Err(e) => errors.push(e),
-            }
-            futures_util::future::ready(())
-        })
-        .await;
-
-    if !errors.is_empty() {
-        eprintln!("Encountered errors:");
-        for error in errors {
-            eprintln!("  {:?}", error);
-        }
-        std::process::exit(1);
+/// Loads the configured repositories, keeping only those matching `--tag`, if any.
+fn selected_repos(args: &Args) -> eyre::Result<Vec<RepoConfig>> {
+    let config = read_default_config()?;
+    Ok(config
+        .repos
+        .into_iter()
+        .filter(|repo| args.tag.is_empty() || repo.tags.iter().any(|tag| args.tag.contains(tag)))
+        .collect())
+}
+
+fn list_repos(args: &Args) -> eyre::Result<()> {
+    let repos = selected_repos(args)?;
+
+    let mut emitter = output::Emitter::new(args.format, "list");
+    if emitter.is_text() {
+        manage::print_repo_list(&repos);
+    }
+    emitter.config_entries(&repos)?;
+    emitter.finish()
+}
+
+async fn show_status(args: &Args) -> eyre::Result<()> {
+    let repos = selected_repos(args)?;
+    let repo_statuses = gather_repo_statuses(&repos, cli::DEFAULT_JOBS.into()).await;
+
+    let mut emitter = output::Emitter::new(args.format, "status");
+    if emitter.is_text() {
+        print_status_table(&repo_statuses);
+    }
+    emitter.statuses(&repo_statuses)?;
+    emitter.finish()
+}
+
+async fn show_log(count: usize, args: &Args) -> eyre::Result<()> {
+    let mut runs = journal::group_runs(journal::read_entries()?);
+    // Most recent first
+    runs.reverse();
+    runs.truncate(count);
+
+    let mut emitter = output::Emitter::new(args.format, "log");
+    if emitter.is_text() {
+        journal::print_runs(&runs).await;
     }
+    let entries: Vec<_> = runs.iter().flatten().collect();
+    emitter.journal_entries(&entries)?;
+    emitter.finish()
+}
 
-    // Sort repo_statuses by path
-    repo_statuses.sort_by(|a, b| a.path.cmp(&b.path));
+async fn sync_repos(
+    mode: SyncMode,
+    consent: &ConsentArgs,
+    exec: &ExecArgs,
+    args: &Args,
+) -> eyre::Result<()> {
+    let repos = selected_repos(args)?;
+    let repo_statuses = gather_repo_statuses(&repos, exec.jobs.into()).await;
 
     // First, create the plan from all gathered data
-    let plan = ExecutionPlan::new(repo_statuses, mode);
+    let mut plan = ExecutionPlan::new(repo_statuses, mode, prompt::is_interactive());
+    if prompt::is_interactive() {
+        if consent.dry_run {
+            plan.defer_picking();
+        } else {
+            plan.pick_files()?;
+        }
+    }
+    plan.flag_files().await;
 
     // Display the summary and plan
     eprintln!("{plan}");
 
+    let mut emitter = output::Emitter::new(args.format, mode.name());
+    emitter.plan(&plan)?;
+
     // If the plan is a no-op, we don't need to ask for consent
     if plan.is_noop() {
         cheer::cheer();
-        return Ok(());
+        return emitter.finish();
+    }
+
+    if consent.dry_run {
+        eprintln!(
+            "\n{} Dry run: none of the commands above were run.",
+            "ℹ️".bright_blue()
+        );
+        return emitter.finish();
     }
 
     // Ask for consent before applying the plan
-    eprint!(
-        "\nDo you want to proceed? Type {} to continue: ",
-        "yes".green()
-    );
-    io::stdout().flush().wrap_err("Failed to flush stdout")?;
-
-    let mut input = String::new();
-    io::stdin()
-        .read_line(&mut input)
-        .wrap_err("Failed to read input")?;
-
-    if input.trim() != "yes" {
-        eprintln!("{}", "Operation cancelled.".red());
-        return Ok(());
+    if !prompt::ask_for_consent(consent.yes, plan.only_pulls())? {
+        return emitter.finish();
     }
 
     // Execute the plan
-    plan.execute().await?;
+    let started = jiff::Timestamp::now();
+    let reports = plan.execute(exec.fail_fast, exec.jobs.into()).await;
+    if let Err(e) = journal::record(started, &plan, &reports) {
+        eprintln!(
+            "{} Couldn't record this run in the journal: {e}",
+            "⚠️".yellow()
+        );
+    }
+    print_results(&reports);
+    emitter.results(&reports)?;
+    emitter.finish()?;
 
-    Ok(())
+    check_reports(&reports)
 }
 
-// RULES:
-// Things that are non-fatal (return Ok(None))
-//   - the directory does not exist
-// Things that should be fatal (return an error)
-//   - the directory is not a git repo
-//   - any of the git gathering commands fail
-async fn get_repo_status(path: &Utf8Path) -> eyre::Result<Option<RepoStatus>> {
-    if !path.exists() {
+async fn undo_last_run(consent: &ConsentArgs, fail_fast: bool) -> eyre::Result<()> {
+    let plan = UndoPlan::new(journal::read_entries()?).await?;
+    eprintln!("{plan}");
+
+    if plan.is_noop() {
+        return Ok(());
+    }
+
+    if consent.dry_run {
         eprintln!(
-            "  {} {} does not exist",
-            "⚠️".yellow(),
-            path.to_string().bright_cyan()
+            "\n{} Dry run: none of the commands above were run.",
+            "ℹ️".bright_blue()
         );
-        return Ok(None);
+        return Ok(());
     }
 
-    if !path.join(".git").is_dir() {
-        return Err(eyre::eyre!(
-            "{} is not a valid git repository",
-            path.to_string().red()
-        ));
+    if !prompt::ask_for_consent(consent.yes, false)? {
+        return Ok(());
     }
 
-    let branch = git::run_git_command_quiet(
-        path,
-        &["rev-parse", "--abbrev-ref", "HEAD"],
-        git::GitCommandBehavior::AssertZeroExitCode,
-    )
-    .await?
-    .stdout
-    .trim()
-    .to_string();
-
-    let remote = git::run_git_command_quiet(
-        path,
-        &["remote", "get-url", "origin"],
-        git::GitCommandBehavior::AssertZeroExitCode,
-    )
-    .await?
-    .stdout
-    .trim()
-    .to_string();
-
-    let status_output = git::run_git_command_quiet(
-        path,
-        &["status", "--porcelain"],
-        git::GitCommandBehavior::AssertZeroExitCode,
-    )
-    .await?;
-
-    let staged_output = git::run_git_command_quiet(
-        path,
-        &["diff", "--cached", "--quiet"],
-        git::GitCommandBehavior::AllowNonZeroExitCode,
-    )
-    .await?;
-
-    let rev_list_output = git::run_git_command_quiet(
-        path,
-        &["rev-list", "@{u}..HEAD"],
-        git::GitCommandBehavior::AssertZeroExitCode,
-    )
-    .await?;
-
-    let fetch_output = git::run_git_command_quiet(
-        path,
-        &["fetch", "--all"],
-        git::GitCommandBehavior::AssertZeroExitCode,
-    )
-    .await?;
-
-    if !fetch_output.status.success() {
-        eprintln!("  {} Failed to fetch changes", "⚠️".yellow());
-        eprintln!("{}", fetch_output.stderr.red());
+    let started = jiff::Timestamp::now();
+    let reports = plan.execute(fail_fast).await;
+    if let Err(e) = journal::record_undo(started, &plan, &reports) {
+        eprintln!(
+            "{} Couldn't record this run in the journal: {e}",
+            "⚠️".yellow()
+        );
     }
-
-    let rev_list_pull_output = git::run_git_command_quiet(
-        path,
-        &["rev-list", "HEAD..@{u}"],
-        git::GitCommandBehavior::AssertZeroExitCode,
-    )
-    .await?;
-
-    Ok(Some(RepoStatus {
-        path: path.to_owned(),
-        branch,
-        remote,
-        has_unstaged_changes: !status_output.stdout.trim().is_empty(),
-        has_staged_changes: staged_output.status.code() == Some(1),
-        has_unpushed_commits: !rev_list_output.stdout.trim().is_empty(),
-        has_unpulled_commits: !rev_list_pull_output.stdout.trim().is_empty(),
-    }))
+    print_results(&reports);
+    check_reports(&reports)
 }
 
-#[derive(Debug)]
-pub(crate) struct RepoStatus {
-    pub(crate) path: Utf8PathBuf,
-    pub(crate) branch: String,
-    pub(crate) remote: String,
-    pub(crate) has_unstaged_changes: bool,
-    pub(crate) has_staged_changes: bool,
-    pub(crate) has_unpushed_commits: bool,
-    pub(crate) has_unpulled_commits: bool,
-}
+async fn show_backups(
+    prune: bool,
+    consent: &ConsentArgs,
+    fail_fast: bool,
+    args: &Args,
+) -> eyre::Result<()> {
+    let repos = selected_repos(args)?;
+    let plan = BackupsPlan::new(&repos, prune).await?;
+    eprintln!("{plan}");
 
-pub(crate) struct ExecutionPlan {
-    pub(crate) repo_plans: Vec<RepoPlan>,
-    pub(crate) mode: SyncMode,
-}
+    let mut emitter = output::Emitter::new(args.format, "backups");
+    emitter.backups(&plan.backups())?;
 
-pub(crate) struct RepoPlan {
-    pub(crate) status: RepoStatus,
-    pub(crate) steps: Vec<ActionStep>,
-}
+    if plan.is_noop() {
+        return emitter.finish();
+    }
+
+    if consent.dry_run {
+        eprintln!(
+            "\n{} Dry run: none of the commands above were run.",
+            "ℹ️".bright_blue()
+        );
+        return emitter.finish();
+    }
+
+    if !prompt::ask_for_consent(consent.yes, false)? {
+        return emitter.finish();
+    }
 
-pub(crate) enum ActionStep {
-    Stage,
-    Commit,
-    Push,
-    Pull,
+    let reports = plan.execute(fail_fast).await;
+    print_results(&reports);
+    emitter.results(&reports)?;
+    emitter.finish()?;
+
+    check_reports(&reports)
 }
 
-impl ExecutionPlan {
-    pub(crate) fn new(repo_statuses: Vec<RepoStatus>, mode: SyncMode) -> Self {
-        let repo_plans = repo_statuses
-            .into_iter()
-            .map(|status| {
-                let mut steps = Vec::new();
-                match mode {
-                    SyncMode::Push => {
-                        if status.has_unstaged_changes {
-                            steps.push(ActionStep::Stage);
-                        }
-                        if status.has_staged_changes || status.has_unstaged_changes {
-                            steps.push(ActionStep::Commit);
-                        }
-                        if status.has_unpushed_commits
-                            || status.has_staged_changes
-                            || status.has_unstaged_changes
-                        {
-                            steps.push(ActionStep::Push);
-                        }
-                    }
-                    SyncMode::Pull => {
-                        if status.has_unpulled_commits {
-                            steps.push(ActionStep::Pull);
-                        }
-                    }
-                }
-                RepoPlan { status, steps }
-            })
-            .collect();
-
-        ExecutionPlan { repo_plans, mode }
+async fn bootstrap_repos(consent: &ConsentArgs, exec: &ExecArgs, args: &Args) -> eyre::Result<()> {
+    let repos = selected_repos(args)?;
+    let plan = BootstrapPlan::new(&repos);
+    eprintln!("{plan}");
+
+    let mut emitter = output::Emitter::new(args.format, "bootstrap");
+    emitter.clones(&plan)?;
+
+    if plan.is_noop() {
+        return emitter.finish();
+    }
+
+    if consent.dry_run {
+        eprintln!(
+            "\n{} Dry run: none of the commands above were run.",
+            "ℹ️".bright_blue()
+        );
+        return emitter.finish();
+    }
+
+    if !prompt::ask_for_consent(consent.yes, false)? {
+        return emitter.finish();
     }
+
+    let reports = plan.execute(exec.fail_fast, exec.jobs.into()).await;
+    print_results(&reports);
+    emitter.results(&reports)?;
+    emitter.finish()?;
+
+    check_reports(&reports)
 }
 
-impl ExecutionPlan {
-    pub(crate) fn is_noop(&self) -> bool {
-        self.repo_plans.iter().all(|plan| plan.steps.is_empty())
+async fn scan_repos(dir: &Utf8Path, max_depth: usize, consent: &ConsentArgs) -> eyre::Result<()> {
+    let root = manage::absolute_path(dir)?;
+    // Missing config files are fine here, we'll create one
+    let configured = config::read_config_if_exists()?.repos;
+    let plan = ScanPlan::new(&root, max_depth, &configured).await?;
+    eprintln!("{plan}");
+
+    if plan.is_noop() {
+        return Ok(());
     }
 
-    pub(crate) async fn execute(&self) -> eyre::Result<()> {
-        for repo_plan in &self.repo_plans {
-            for step in &repo_plan.steps {
-                match step {
-                    ActionStep::Stage => {
-                        git::assert_git_command(&repo_plan.status.path, &["add", "."]).await?;
-                    }
-                    ActionStep::Commit => {
-                        // Show git diff of staged changes
-                        let diff_output =
-                            git::assert_git_command(&repo_plan.status.path, &["diff", "--cached"])
-                                .await?;
-                        eprintln!("Staged changes:");
-                        eprintln!("{}", diff_output.stdout);
-
-                        // Wait for user to press Enter
-                        eprintln!("Press Enter to continue with commit...");
-                        let mut input = String::new();
-                        std::io::stdin().read_line(&mut input)?;
-
-                        // We can't use assert_git_command here because 'git commit' opens a text editor,
-                        // which requires inheriting the standard input. We need to run it manually.
-                        let status = tokio::process::Command::new("git")
-                            .current_dir(&repo_plan.status.path)
-                            .arg("commit")
-                            .status()
-                            .await?;
-
-                        if !status.success() {
-                            return Err(eyre::eyre!("Git commit failed"));
-                        }
-                    }
-                    ActionStep::Push => {
-                        git::assert_git_command(&repo_plan.status.path, &["push"]).await?;
-                    }
-                    ActionStep::Pull => {
-                        git::assert_git_command(&repo_plan.status.path, &["pull"]).await?;
-                    }
-                }
-            }
-        }
-        Ok(())
+    if consent.dry_run {
+        eprintln!(
+            "\n{} Dry run: the config was left untouched.",
+            "ℹ️".bright_blue()
+        );
+        return Ok(());
+    }
+
+    if !prompt::ask_for_consent(consent.yes, false)? {
+        return Ok(());
     }
+
+    plan.execute(&configured)
 }
 
-impl fmt::Display for ExecutionPlan {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(
-            f,
-            "\n{} Plan:",
-            match self.mode {
-                SyncMode::Pull => "Pull",
-                SyncMode::Push => "Push",
-            }
-            .bright_cyan()
-        )?;
-
-        for repo_plan in &self.repo_plans {
-            let status = &repo_plan.status;
-            let display_path = if let Some(home_dir) = dirs::home_dir() {
-                status
-                    .path
-                    .strip_prefix(&home_dir)
-                    .map(|p| Utf8PathBuf::from("~").join(p))
-                    .unwrap_or_else(|_| status.path.clone())
-            } else {
-                status.path.clone()
-            };
-            let normalized_remote = normalize_remote(&status.remote);
-            let emoji = if status.has_unstaged_changes {
-                "🔄"
-            } else if status.has_staged_changes {
-                "📦"
-            } else if status.has_unpushed_commits {
-                "⬆️"
-            } else if status.has_unpulled_commits {
-                "⬇️"
-            } else {
-                "✅"
-            };
-
-            writeln!(
-                f,
-                "{} {} {} @ {}",
-                emoji,
-                display_path.bright_cyan(),
-                status.branch.bright_green(),
-                normalized_remote
-            )?;
-
-            let mut actions = Vec::new();
-            if status.has_unstaged_changes {
-                actions.push("Needs staging".style(Style::new().bright_red()));
-            }
-            if status.has_staged_changes {
-                actions.push("Needs commit".style(Style::new().bright_yellow()));
-            }
-            if status.has_unpushed_commits {
-                actions.push("Needs push".style(Style::new().bright_blue()));
-            }
-            if status.has_unpulled_commits {
-                actions.push("Needs pull".style(Style::new().bright_magenta()));
-            }
-
-            if !actions.is_empty() {
-                for (i, action) in actions.iter().enumerate() {
-                    if i > 0 {
-                        write!(f, ", ")?;
-                    }
-                    write!(f, "{}", action)?;
-                }
-                writeln!(f)?;
-            }
-
-            for step in &repo_plan.steps {
-                match step {
-                    ActionStep::Stage => {
-                        writeln!(f, "  {}: git add .", "Will execute".bright_blue())?
-                    }
-                    ActionStep::Commit => {
-                        writeln!(f, "  {}: git commit", "Will execute".bright_blue())?
-                    }
-                    ActionStep::Push => {
-                        writeln!(f, "  {}: git push", "Will execute".bright_blue())?
-                    }
-                    ActionStep::Pull => {
-                        writeln!(f, "  {}: git pull", "Will execute".bright_blue())?
-                    }
-                }
-            }
-        }
+/// Turns failed repositories into an error, so grit exits with a non-zero code.
+fn check_reports(reports: &[RepoReport]) -> eyre::Result<()> {
+    let failed = reports
+        .iter()
+        .filter(|report| matches!(report.outcome, RepoOutcome::Failed { .. }))
+        .count();
+    if failed > 0 {
+        return Err(eyre::eyre!(
+            "{} repositories failed to sync",
+            failed.to_string().bright_red()
+        ));
+    }
 
-        Ok(())
+    Ok(())
+}
+
+/// Shortens paths under the home directory to `~/...` for display
+pub(crate) fn display_path(path: &Utf8Path) -> Utf8PathBuf {
+    if let Some(home_dir) = dirs::home_dir() {
+        path.strip_prefix(&home_dir)
+            .map(|p| Utf8PathBuf::from("~").join(p))
+            .unwrap_or_else(|_| path.to_owned())
+    } else {
+        path.to_owned()
     }
 }
 
 /// turns `https://github.com/fasterthanlime/blah` into `gh:fasterthanlime/blah`
 /// turns `https://code.bearcove.cloud/amos/bar` into `bcc:amos/bar`
-fn normalize_remote(remote: &str) -> String {
+pub(crate) fn normalize_remote(remote: &str) -> String {
     let remote = remote.strip_suffix(".git").unwrap_or(remote);
     if let Some(github_path) = remote.strip_prefix("https://github.com/") {
         format!("{}{}", "gh:".bright_blue(), github_path.bright_yellow())
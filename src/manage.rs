@@ -0,0 +1,194 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! `grit add`, `grit remove` and `grit list`: managing the config from the
+//! command line, so nobody has to hand-edit it.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+
+use crate::config::{self, RepoConfig};
+use crate::{display_path, git};
+
+/// Turns a path given on the command line into an absolute one, with `~` expanded.
+pub(crate) fn absolute_path(path: &Utf8Path) -> eyre::Result<Utf8PathBuf> {
+    let expanded = shellexpand::tilde(path.as_str()).to_string();
+    let absolute = std::path::absolute(&expanded)?;
+    Utf8PathBuf::try_from(absolute).map_err(|e| eyre::eyre!("Path is not valid UTF-8: {e}"))
+}
+
+/// Details about a repository, as needed to record it in the config
+pub(crate) struct DiscoveredRepo {
+    /// The top-level directory of the working tree
+    pub(crate) path: Utf8PathBuf,
+    /// The URL of `origin`, if there's such a remote
+    pub(crate) url: Option<String>,
+}
+
+/// Checks that `path` is inside a git working tree, and finds its top-level
+/// directory and `origin` URL.
+pub(crate) async fn inspect_repo(path: &Utf8Path) -> eyre::Result<DiscoveredRepo> {
+    let toplevel = git::run_git_command_quiet(
+        path,
+        &["rev-parse", "--show-toplevel"],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    if !toplevel.status.success() {
+        return Err(eyre::eyre!(
+            "{} is not inside a git repository",
+            path.to_string().red()
+        ));
+    }
+    let toplevel = Utf8PathBuf::from(toplevel.stdout.trim());
+
+    let url = git::run_git_command_quiet(
+        &toplevel,
+        &["remote", "get-url", "origin"],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    let url = url
+        .status
+        .success()
+        .then(|| url.stdout.trim().to_string())
+        .filter(|url| !url.is_empty());
+
+    Ok(DiscoveredRepo {
+        path: toplevel,
+        url,
+    })
+}
+
+/// Records a repository in the config, unless it's already there.
+///
+/// Returns false if it was already configured.
+pub(crate) fn record_repo(repo: &DiscoveredRepo, configured: &[RepoConfig]) -> eyre::Result<bool> {
+    if configured.iter().any(|entry| entry.path == repo.path) {
+        eprintln!(
+            "{} {} is already in the config",
+            "ℹ️".bright_blue(),
+            display_path(&repo.path).bright_cyan()
+        );
+        return Ok(false);
+    }
+
+    let stored_path = display_path(&repo.path).to_string();
+    let config_file = config::add_repo_to_default_config(&stored_path, repo.url.as_deref())?;
+    eprintln!(
+        "{} Added {} to {}",
+        "✅".green(),
+        stored_path.bright_cyan(),
+        config_file.bright_cyan()
+    );
+    if let Some(url) = &repo.url {
+        if config_file.extension() == Some("conf") {
+            eprintln!(
+                "  {} The legacy config format can't record its url ({}), so {} won't be able to clone it",
+                "⚠️".yellow(),
+                url.bright_yellow(),
+                "grit bootstrap".bright_green()
+            );
+        }
+    } else {
+        eprintln!(
+            "  {} It has no {} remote, so there's no url to clone it from",
+            "⚠️".yellow(),
+            "origin".bright_yellow()
+        );
+    }
+    Ok(true)
+}
+
+pub(crate) async fn add_repo(path: Option<&Utf8Path>) -> eyre::Result<()> {
+    let path = match path {
+        Some(path) => absolute_path(path)?,
+        None => absolute_path(Utf8Path::new("."))?,
+    };
+    if !path.is_dir() {
+        return Err(eyre::eyre!(
+            "{} is not a directory",
+            display_path(&path).to_string().red()
+        ));
+    }
+    let repo = inspect_repo(&path).await?;
+
+    // Missing config files are fine here, we'll create one
+    let configured = config::read_config_if_exists()?.repos;
+    record_repo(&repo, &configured)?;
+    Ok(())
+}
+
+pub(crate) fn remove_repo(path: &Utf8Path) -> eyre::Result<()> {
+    let path = absolute_path(path)?;
+    match config::remove_repo_from_default_config(&path)? {
+        Some(config_file) => {
+            eprintln!(
+                "{} Removed {} from {}",
+                "✅".green(),
+                display_path(&path).bright_cyan(),
+                config_file.bright_cyan()
+            );
+            Ok(())
+        }
+        None => Err(eyre::eyre!(
+            "{} is not in the config",
+            display_path(&path).to_string().red()
+        )),
+    }
+}
+
+/// Prints every configured repository along with its options.
+pub(crate) fn print_repo_list(repos: &[RepoConfig]) {
+    eprintln!("\n{}", "Repositories:".bright_cyan());
+    for repo in repos {
+        let emoji = if repo.path.exists() { "📁" } else { "❓" };
+        eprint!("{} {}", emoji, display_path(&repo.path).bright_cyan());
+        if !repo.tags.is_empty() {
+            eprint!(
+                " {}",
+                format!("[{}]", repo.tags.join(", ")).bright_magenta()
+            );
+        }
+        eprintln!();
+
+        let mut options = Vec::new();
+        if let Some(url) = &repo.url {
+            options.push(format!("url: {}", crate::normalize_remote(url)));
+        }
+        if let Some(remote) = &repo.remote {
+            options.push(format!("remote: {}", remote.bright_yellow()));
+        }
+        if let Some(branch) = &repo.branch {
+            options.push(format!("branch: {}", branch.bright_green()));
+        }
+        if let Some(strategy) = &repo.pull_strategy {
+            options.push(format!("pull: {}", strategy.bright_yellow()));
+        }
+        if let Some(on_diverged) = &repo.on_diverged {
+            options.push(format!("on diverged: {}", on_diverged.bright_yellow()));
+        }
+        if let Some(on_dirty) = &repo.on_dirty {
+            options.push(format!("on dirty: {}", on_dirty.bright_yellow()));
+        }
+        if !repo.push {
+            options.push(format!("push: {}", "no".red()));
+        }
+        if let Some(mode) = &repo.commit_mode {
+            options.push(format!("commit: {}", mode.bright_yellow()));
+        }
+        if !options.is_empty() {
+            eprintln!("  {}", options.join(", "));
+        }
+    }
+    eprintln!(
+        "\n{} repositories configured",
+        repos.len().to_string().bright_yellow()
+    );
+}
@@ -0,0 +1,240 @@
+// Rules:
+// 1. Always use eprintln!(), not println!() (machine-readable output is the one exception, it goes to stdout)
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! Machine-readable output for `--format json` and `--format ndjson`.
+//!
+//! The schema is documented in the README. Bump [`SCHEMA_VERSION`] on any
+//! change that isn't purely additive.
+
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+use crate::backups::Backup;
+use crate::bootstrap::BootstrapPlan;
+use crate::cli::OutputFormat;
+use crate::config::RepoConfig;
+use crate::journal::JournalEntry;
+use crate::plan::{ExecutionPlan, RepoReport};
+use crate::status::RepoStatus;
+
+pub(crate) const SCHEMA_VERSION: u32 = 2;
+
+/// Writes statuses, plans and results to stdout in the requested format.
+///
+/// In `json` mode everything is collected into a single document that is
+/// printed by [`Emitter::finish`]. In `ndjson` mode every record is printed
+/// as soon as it's known, one object per line, tagged with a `type` field.
+/// In `text` mode nothing is written: the human-readable output on stderr
+/// is all there is.
+pub(crate) struct Emitter {
+    format: OutputFormat,
+    document: Map<String, Value>,
+}
+
+impl Emitter {
+    pub(crate) fn new(format: OutputFormat, command: &str) -> Self {
+        let mut document = Map::new();
+        document.insert("version".into(), json!(SCHEMA_VERSION));
+        document.insert("command".into(), json!(command));
+        Self { format, document }
+    }
+
+    pub(crate) fn is_text(&self) -> bool {
+        matches!(self.format, OutputFormat::Text)
+    }
+
+    pub(crate) fn statuses(&mut self, statuses: &[RepoStatus]) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                self.document
+                    .insert("repos".into(), serde_json::to_value(statuses)?);
+            }
+            OutputFormat::Ndjson => {
+                for status in statuses {
+                    print_record("status", status)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn config_entries(&mut self, repos: &[RepoConfig]) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                self.document
+                    .insert("repos".into(), serde_json::to_value(repos)?);
+            }
+            OutputFormat::Ndjson => {
+                for repo in repos {
+                    print_record("repo", repo)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn journal_entries(&mut self, entries: &[&JournalEntry]) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                self.document
+                    .insert("entries".into(), serde_json::to_value(entries)?);
+            }
+            OutputFormat::Ndjson => {
+                for entry in entries {
+                    print_record("entry", entry)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn backups(&mut self, backups: &[&Backup]) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                self.document
+                    .insert("backups".into(), serde_json::to_value(backups)?);
+            }
+            OutputFormat::Ndjson => {
+                for backup in backups {
+                    print_record("backup", backup)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The clones `grit bootstrap` plans, and the repositories it can't
+    /// clone, with a `reason` instead of a `command`
+    pub(crate) fn clones(&mut self, plan: &BootstrapPlan) -> eyre::Result<()> {
+        let mut clones = Vec::new();
+        for clone in &plan.clones {
+            clones.push(serde_json::to_value(clone)?);
+        }
+        for (path, reason) in &plan.skipped {
+            clones.push(json!({ "path": path, "reason": reason }));
+        }
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                self.document.insert("clones".into(), Value::Array(clones));
+                // Stays null unless the plan actually gets executed
+                self.document.insert("results".into(), Value::Null);
+            }
+            OutputFormat::Ndjson => {
+                for clone in &clones {
+                    print_record("clone", clone)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn plan(&mut self, plan: &ExecutionPlan) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                self.document
+                    .insert("plan".into(), serde_json::to_value(&plan.repo_plans)?);
+                // Stays null unless the plan actually gets executed
+                self.document.insert("results".into(), Value::Null);
+            }
+            OutputFormat::Ndjson => {
+                for repo_plan in &plan.repo_plans {
+                    print_record("plan", repo_plan)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn results(&mut self, reports: &[RepoReport]) -> eyre::Result<()> {
+        match self.format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                self.document
+                    .insert("results".into(), serde_json::to_value(reports)?);
+            }
+            OutputFormat::Ndjson => {
+                for report in reports {
+                    print_record("result", report)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the collected document, in `json` mode. Must be called on every
+    /// exit path once the emitter was created.
+    pub(crate) fn finish(self) -> eyre::Result<()> {
+        if let OutputFormat::Json = self.format {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &self.document)?;
+            writeln!(stdout)?;
+        }
+        Ok(())
+    }
+}
+
+fn print_record(kind: &str, record: &impl Serialize) -> eyre::Result<()> {
+    let mut value = serde_json::to_value(record)?;
+    let Value::Object(fields) = &mut value else {
+        return Err(eyre::eyre!("ndjson records must serialize to objects"));
+    };
+
+    let mut line = Map::new();
+    line.insert("version".into(), json!(SCHEMA_VERSION));
+    line.insert("type".into(), json!(kind));
+    line.append(fields);
+
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &line)?;
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Removes ANSI escape sequences, so colored error messages can be embedded
+/// in machine-readable output.
+pub(crate) fn strip_ansi(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Skip parameters until the final byte, which is in '@'..='~'
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use owo_colors::OwoColorize;
+
+    #[test]
+    fn test_strip_ansi() {
+        let colored = format!("{} failed in {}", "git push".bright_cyan(), "~/a".red());
+        assert_eq!(strip_ansi(&colored), "git push failed in ~/a");
+        assert_eq!(strip_ansi("plain text"), "plain text");
+    }
+}
@@ -0,0 +1,1964 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+use std::cell::Cell;
+use std::fmt;
+
+use camino::Utf8PathBuf;
+use futures_util::StreamExt;
+use indicatif::ProgressBar;
+use jiff::Timestamp;
+use owo_colors::{OwoColorize, Style};
+use serde::{Deserialize, Serialize};
+
+use crate::backups;
+use crate::branches::BranchStatus;
+use crate::cli::SyncMode;
+use crate::commit::{self, TemplateValues};
+use crate::config::{CommitMode, OnDirty, OnDiverged, PullStrategy, RepoConfig, Staging};
+use crate::git::{self, GitInvocation};
+use crate::guard;
+use crate::progress::{self, RepoProgress};
+use crate::staging::{self, StagingChoice};
+use crate::status::{FailedFetch, RepoStatus};
+use crate::{display_path, output};
+
+pub(crate) struct ExecutionPlan {
+    pub(crate) repo_plans: Vec<RepoPlan>,
+    pub(crate) mode: SyncMode,
+    /// Whether steps that need a human were planned
+    interactive: bool,
+    /// Whether the files to stage with `staging = "pick"` are left to pick
+    /// when the plan is applied, as in dry runs
+    picking_deferred: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RepoPlan {
+    pub(crate) status: RepoStatus,
+    pub(crate) steps: Vec<PlannedStep>,
+    /// Explanations for anything the plan decided not to do, as plain text
+    pub(crate) notes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ActionStep {
+    /// Saves HEAD and uncommitted changes under `refs/grit/backup`, before
+    /// the steps that could lose them
+    Backup,
+    Stage,
+    Commit,
+    Push,
+    Pull,
+    /// Pulls diverged upstream commits in with `on_diverged`'s strategy
+    Reconcile,
+    /// Fast-forwards branches from `branches` that aren't checked out
+    FastForward,
+    /// Pushes branches from `branches` that aren't checked out
+    PushBranches,
+    /// Pushes to the remotes from `push_remotes`
+    PushRemotes,
+}
+
+impl ActionStep {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ActionStep::Backup => "back up",
+            ActionStep::Stage => "stage",
+            ActionStep::Commit => "commit",
+            ActionStep::Push => "push",
+            ActionStep::Pull => "pull",
+            ActionStep::Reconcile => "reconcile",
+            ActionStep::FastForward => "fast-forward branches",
+            ActionStep::PushBranches => "push branches",
+            ActionStep::PushRemotes => "push to other remotes",
+        }
+    }
+
+    /// The exact git commands this step runs for the given repository.
+    /// `committing` says whether the plan commits first, giving the checked
+    /// out branch something new to push.
+    fn commands(&self, status: &RepoStatus, committing: bool) -> Vec<GitInvocation> {
+        let path = status.path.as_path();
+        match self {
+            ActionStep::Backup => {
+                let branch = status
+                    .branch
+                    .as_deref()
+                    .expect("only repositories with a branch checked out get steps");
+                let refname = backups::ref_name(Timestamp::now(), branch);
+                // An empty old value stands for the zero OID, whatever the
+                // hash: git refuses to overwrite an existing backup
+                let mut commands = vec![GitInvocation::snapshot(
+                    path,
+                    &[
+                        "update-ref",
+                        "-m",
+                        backups::SNAPSHOT_MESSAGE,
+                        &refname,
+                        git::SNAPSHOT_ARG,
+                        "",
+                    ],
+                )];
+                // The new backup takes the place of the oldest one kept
+                let keep = status.config.keep_backups().saturating_sub(1);
+                commands.extend(
+                    status
+                        .backups
+                        .iter()
+                        .skip(keep)
+                        .map(|backup| backups::prune_command(path, backup)),
+                );
+                commands
+            }
+            ActionStep::Stage => {
+                // `-A` stages deletions too. Paths are taken literally, so a
+                // file named `*` can't stage everything
+                let mut args = vec!["--literal-pathspecs", "add", "-A", "--"];
+                args.extend(StagingChoice::new(status).to_stage);
+                vec![GitInvocation::new(path, &args)]
+            }
+            ActionStep::Commit => match status.config.commit_mode() {
+                CommitMode::Editor => vec![
+                    // Show the staged changes and wait for Enter before committing
+                    GitInvocation::review(path, &["diff", "--cached"]),
+                    // 'git commit' opens a text editor, which requires inheriting the terminal
+                    GitInvocation::interactive(path, &["commit"]),
+                ],
+                CommitMode::Prompt => vec![
+                    // A summary is enough to come up with a one-line message
+                    GitInvocation::new(path, &["diff", "--cached", "--stat"]),
+                    GitInvocation::prompt(path, &["commit", "-m"]),
+                ],
+                CommitMode::Auto => {
+                    let values =
+                        TemplateValues::current(StagingChoice::new(status).committed.len());
+                    let message =
+                        commit::expand_template(status.config.commit_message_template(), &values)
+                            .expect("commit message templates are checked when reading the config");
+                    vec![GitInvocation::new(path, &["commit", "-m", &message])]
+                }
+            },
+            ActionStep::Push => match (&status.upstream, &status.branch) {
+                // A new branch: publish it, and track it from now on
+                (None, Some(branch)) => vec![GitInvocation::new(
+                    path,
+                    &["push", "-u", &status.remote_name, branch],
+                )],
+                _ => vec![GitInvocation::new(path, &["push"])],
+            },
+            ActionStep::Pull => vec![pull_command(
+                status,
+                status.config.pull_strategy(),
+                committing,
+            )],
+            ActionStep::Reconcile => {
+                let strategy = status
+                    .config
+                    .diverged_pull_strategy()
+                    .expect("reconcile is only planned when there's a strategy for it");
+                vec![pull_command(status, strategy, false)]
+            }
+            ActionStep::FastForward => {
+                // Fetching from the repository itself updates a branch
+                // without checking it out, and only if it fast-forwards
+                let refspecs: Vec<String> = fast_forwards(status)
+                    .map(|branch| {
+                        let upstream = branch.upstream_ref.as_ref().expect("checked above");
+                        format!("{}:refs/heads/{}", upstream.local_ref, branch.name)
+                    })
+                    .collect();
+                let mut args = vec!["fetch", "."];
+                args.extend(refspecs.iter().map(String::as_str));
+                vec![GitInvocation::new(path, &args)]
+            }
+            ActionStep::PushBranches => {
+                let mut by_remote: Vec<(&str, Vec<String>)> = Vec::new();
+                for branch in branch_pushes(status) {
+                    let upstream = branch.upstream_ref.as_ref().expect("checked above");
+                    let refspec = if upstream.remote_branch == branch.name {
+                        branch.name.clone()
+                    } else {
+                        format!("{}:{}", branch.name, upstream.remote_branch)
+                    };
+                    match by_remote
+                        .iter_mut()
+                        .find(|(remote, _)| *remote == upstream.remote)
+                    {
+                        Some((_, refspecs)) => refspecs.push(refspec),
+                        None => by_remote.push((&upstream.remote, vec![refspec])),
+                    }
+                }
+                by_remote
+                    .iter()
+                    .map(|(remote, refspecs)| {
+                        let mut args = vec!["push", remote];
+                        args.extend(refspecs.iter().map(String::as_str));
+                        GitInvocation::new(path, &args)
+                    })
+                    .collect()
+            }
+            ActionStep::PushRemotes => remote_pushes(status, committing)
+                .map(|(remote, branches)| {
+                    let mut args = vec!["push", remote];
+                    args.extend(branches);
+                    GitInvocation::new(path, &args)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The branches from `branches` that can be fast-forwarded
+pub(crate) fn fast_forwards(status: &RepoStatus) -> impl Iterator<Item = &BranchStatus> {
+    status
+        .branches
+        .iter()
+        .filter(|branch| branch.can_fast_forward() && branch_fetch_failed(status, branch).is_none())
+}
+
+/// The branches from `branches` that can be pushed to their upstream
+pub(crate) fn branch_pushes(status: &RepoStatus) -> impl Iterator<Item = &BranchStatus> {
+    status
+        .branches
+        .iter()
+        .filter(|branch| branch.can_push() && branch_fetch_failed(status, branch).is_none())
+}
+
+/// The remotes from `push_remotes` that are missing commits, with the
+/// branches to push to each. Branches the remote has commits of that we
+/// don't are left out, since pushing them would be rejected.
+pub(crate) fn remote_pushes(
+    status: &RepoStatus,
+    committing: bool,
+) -> impl Iterator<Item = (&str, Vec<&str>)> {
+    status
+        .push_remotes
+        .iter()
+        .filter(|remote| remote.exists && failed_fetch(status, &remote.remote).is_none())
+        .filter_map(move |remote| {
+            let branches: Vec<&str> = remote
+                .branches
+                .iter()
+                .filter(|branch| branch.behind == 0)
+                .filter(|branch| {
+                    branch.ahead > 0 || (committing && status.branch.as_ref() == Some(&branch.name))
+                })
+                .map(|branch| branch.name.as_str())
+                .collect();
+            (!branches.is_empty()).then_some((remote.remote.as_str(), branches))
+        })
+}
+
+/// The failed fetch of the remote a branch from `branches` tracks, if it
+/// failed
+fn branch_fetch_failed<'a>(
+    status: &'a RepoStatus,
+    branch: &BranchStatus,
+) -> Option<&'a FailedFetch> {
+    let upstream = branch.upstream_ref.as_ref()?;
+    failed_fetch(status, &upstream.remote)
+}
+
+fn failed_fetch<'a>(status: &'a RepoStatus, remote: &str) -> Option<&'a FailedFetch> {
+    status
+        .failed_fetches
+        .iter()
+        .find(|fetch| fetch.remote == remote)
+}
+
+/// `git pull` with the given strategy. Uncommitted changes are stashed and
+/// restored around it when the config says `on_dirty = "autostash"`.
+fn pull_command(status: &RepoStatus, strategy: PullStrategy, committing: bool) -> GitInvocation {
+    let mut args = vec!["pull"];
+    args.extend(strategy.pull_args());
+    // Changes committed after the pull are stashed out of its way meanwhile
+    let stashes = (status.is_dirty() && status.config.on_dirty() == OnDirty::Autostash)
+        || (committing && status.has_tracked_changes());
+    if stashes && !strategy.autostashes() {
+        args.push("--autostash");
+    }
+    GitInvocation::new(&status.path, &args)
+}
+
+/// A step along with the commands that carry it out. These commands are both
+/// what the plan displays and what gets executed, so the two can't drift.
+#[derive(Serialize)]
+pub(crate) struct PlannedStep {
+    pub(crate) action: ActionStep,
+    pub(crate) commands: Vec<GitInvocation>,
+}
+
+impl PlannedStep {
+    fn new(action: ActionStep, status: &RepoStatus, committing: bool) -> Self {
+        Self {
+            action,
+            commands: action.commands(status, committing),
+        }
+    }
+}
+
+/// What happened to a repository when the plan was executed
+#[derive(Serialize)]
+pub(crate) struct RepoReport {
+    pub(crate) path: Utf8PathBuf,
+    #[serde(flatten)]
+    pub(crate) outcome: RepoOutcome,
+    /// The commit HEAD pointed to before the steps ran, `None` if they
+    /// didn't run or there was no commit yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) old_head: Option<String>,
+    /// The commit HEAD points to after the steps ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) new_head: Option<String>,
+    /// The branches from `branches` that the steps moved
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) branches: Vec<BranchMove>,
+    /// The uncommitted changes to tracked files the steps left, as
+    /// `git status --porcelain` lines, for `grit undo` to tell whether the
+    /// working tree changed since
+    #[serde(skip)]
+    pub(crate) tracked_changes: Vec<String>,
+}
+
+impl RepoReport {
+    /// A report for a repository whose steps didn't run, or that has no
+    /// commits to speak of
+    pub(crate) fn new(path: Utf8PathBuf, outcome: RepoOutcome) -> Self {
+        Self {
+            path,
+            outcome,
+            old_head: None,
+            new_head: None,
+            branches: Vec::new(),
+            tracked_changes: Vec::new(),
+        }
+    }
+}
+
+/// Where a branch pointed before and after the steps ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BranchMove {
+    pub(crate) branch: String,
+    pub(crate) old: Option<String>,
+    pub(crate) new: Option<String>,
+}
+
+/// The commits HEAD and the branches from `branches` point to
+struct RefSnapshot {
+    head: Option<String>,
+    branches: Vec<(String, Option<String>)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub(crate) enum RepoOutcome {
+    Succeeded,
+    Failed { step: String, error: String },
+    Skipped { reason: String },
+}
+
+/// Prints one line per repository saying how it went, then the totals.
+pub(crate) fn print_results(reports: &[RepoReport]) {
+    if reports.is_empty() {
+        return;
+    }
+
+    let path_width = reports
+        .iter()
+        .map(|report| display_path(&report.path).as_str().len())
+        .max()
+        .unwrap_or(0);
+
+    eprintln!("\n{}", "Results:".bright_cyan());
+    let (mut succeeded, mut failed, mut skipped) = (0, 0, 0);
+    for report in reports {
+        let path = format!("{:path_width$}", display_path(&report.path))
+            .bright_cyan()
+            .to_string();
+        match &report.outcome {
+            RepoOutcome::Succeeded => {
+                succeeded += 1;
+                eprintln!("  {path}  {} {}", "✅".green(), "succeeded".green());
+            }
+            RepoOutcome::Failed { step, error } => {
+                failed += 1;
+                // The full error was printed when it happened
+                let first_line = error.lines().next().unwrap_or_default();
+                eprintln!(
+                    "  {path}  {} {} {}: {}",
+                    "❌".red(),
+                    "failed to".bright_red(),
+                    step.bright_yellow(),
+                    first_line.red()
+                );
+            }
+            RepoOutcome::Skipped { reason } => {
+                skipped += 1;
+                eprintln!(
+                    "  {path}  {} {}: {}",
+                    "⏭️".yellow(),
+                    "skipped".yellow(),
+                    reason
+                );
+            }
+        }
+    }
+
+    eprintln!(
+        "\n{} succeeded, {} failed, {} skipped",
+        succeeded.to_string().green(),
+        failed.to_string().bright_red(),
+        skipped.to_string().yellow()
+    );
+}
+
+impl ExecutionPlan {
+    /// Builds the plan. When `interactive` is false, steps that need a human
+    /// (like committing through an editor) are left out, with a note.
+    pub(crate) fn new(repo_statuses: Vec<RepoStatus>, mode: SyncMode, interactive: bool) -> Self {
+        let repo_plans = repo_statuses
+            .into_iter()
+            .map(|status| RepoPlan::new(status, mode, interactive))
+            .collect();
+
+        ExecutionPlan {
+            repo_plans,
+            mode,
+            interactive,
+            picking_deferred: false,
+        }
+    }
+
+    /// Flags the files each repository is about to commit, then plans the
+    /// repositories with flagged files again, which leaves them alone. Meant
+    /// to be called once the files to commit are settled, before the plan is
+    /// shown.
+    pub(crate) async fn flag_files(&mut self) {
+        for repo_plan in &mut self.repo_plans {
+            let commits = repo_plan
+                .steps
+                .iter()
+                .any(|step| step.action == ActionStep::Commit);
+            if !commits {
+                continue;
+            }
+
+            let mut status = repo_plan.status.clone();
+            let staging = StagingChoice::new(&status);
+            let committed: Vec<String> = staging
+                .committed
+                .iter()
+                .map(|file| file.path.clone())
+                .collect();
+            let to_stage: Vec<String> = staging
+                .to_stage
+                .iter()
+                .map(|path| path.to_string())
+                .collect();
+            guard::flag_files(
+                &status.path,
+                &mut status.changed_files,
+                &committed,
+                &to_stage,
+                &status.config,
+            )
+            .await;
+            if status
+                .changed_files
+                .iter()
+                .any(|file| !file.flags.is_empty())
+            {
+                *repo_plan = RepoPlan::new(status, self.mode, self.interactive);
+            }
+        }
+    }
+
+    /// With `staging = "pick"`, asks which files to stage in each repository
+    /// that's about to stage some, then plans it again without the files left
+    /// out. Meant to be called before the plan is shown, when stdin is a
+    /// terminal.
+    pub(crate) fn pick_files(&mut self) -> eyre::Result<()> {
+        for repo_plan in &mut self.repo_plans {
+            let stages = repo_plan
+                .steps
+                .iter()
+                .any(|step| step.action == ActionStep::Stage);
+            if !stages || repo_plan.status.config.staging() != Staging::Pick {
+                continue;
+            }
+
+            let mut status = repo_plan.status.clone();
+            let to_stage = StagingChoice::new(&status).to_stage;
+            let files: Vec<_> = status
+                .changed_files
+                .iter()
+                .filter(|file| to_stage.contains(&file.path.as_str()))
+                .collect();
+            let left_out = staging::ask_left_out(&status, &files)?;
+            status.left_out = left_out;
+            *repo_plan = RepoPlan::new(status, self.mode, self.interactive);
+        }
+        Ok(())
+    }
+
+    /// Rather than asking which files to stage, as `pick_files` does, shows
+    /// them as to be picked, for dry runs.
+    pub(crate) fn defer_picking(&mut self) {
+        self.picking_deferred = true;
+    }
+}
+
+impl RepoPlan {
+    fn new(status: RepoStatus, mode: SyncMode, interactive: bool) -> Self {
+        let mut actions = Vec::new();
+        let mut notes = Vec::new();
+        let config = &status.config;
+
+        if status.branch.is_none() {
+            notes.push("Skipping: HEAD is detached, check out a branch to sync it".to_string());
+        } else if status.remote.is_none() {
+            notes.push(format!(
+                "Skipping: there's no remote named {} to sync with",
+                status.remote_name
+            ));
+        } else if let Some(upstream_remote) = status
+            .upstream_remote
+            .as_deref()
+            .filter(|remote| *remote != status.remote_name)
+        {
+            // A plain `git pull` or `git push` would go to the upstream's
+            // remote, and that's not the one the config asks for
+            notes.push(format!(
+                "Skipping: {} tracks {}, on {}, but the config syncs with {}. Run `git branch --set-upstream-to {}/{}` to follow it",
+                status.branch_label(),
+                status.upstream.as_deref().unwrap_or_default(),
+                upstream_remote,
+                status.remote_name,
+                status.remote_name,
+                status.branch_label()
+            ));
+        } else if let Some(branch) = config
+            .branch
+            .as_deref()
+            .filter(|b| Some(*b) != status.branch.as_deref())
+        {
+            notes.push(format!(
+                "Skipping: {} is checked out, but the config only syncs {}",
+                status.branch_label(),
+                branch
+            ));
+        } else {
+            let staging = StagingChoice::new(&status);
+            match mode {
+                SyncMode::Push => {
+                    let needs_commit = status.is_dirty();
+                    if !config.push {
+                        notes.push("Not pushing: the config says push = false".to_string());
+                    } else if let Some(fetch) = status.stale_fetch() {
+                        notes.push(format!(
+                            "Not pushing: fetching {} failed ({}), so we can't tell what's new",
+                            fetch.remote, fetch.error
+                        ));
+                    } else {
+                        let blocker = needs_commit
+                            .then(|| commit_blocker(&status, &staging, interactive))
+                            .flatten();
+                        if let Some(why) = &blocker {
+                            notes.push(format!("Not committing local changes: {why}"));
+                        }
+                        let committing = needs_commit && blocker.is_none();
+                        let local = status.ahead + usize::from(committing);
+                        if local > 0
+                            && status.has_unpulled_commits()
+                            && config.diverged_pull_strategy().is_none()
+                        {
+                            // The push would be rejected, so a commit would
+                            // only make the branch diverge further
+                            notes.push(diverged_note(&status, "pushing"));
+                        } else if local > 0 {
+                            if committing {
+                                plan_commit(&staging, &mut actions, &mut notes);
+                            }
+                            if status.has_unpulled_commits() {
+                                // The push would be rejected: reconcile first
+                                actions.push(ActionStep::Reconcile);
+                            }
+                            actions.push(ActionStep::Push);
+                        }
+                    }
+                }
+                SyncMode::Sync => {
+                    plan_sync(&status, &staging, interactive, &mut actions, &mut notes)
+                }
+                SyncMode::Pull => {
+                    if let Some(fetch) = status.stale_fetch() {
+                        notes.push(format!(
+                            "Not pulling: fetching {} failed ({})",
+                            fetch.remote, fetch.error
+                        ));
+                    } else if status.upstream.is_none() {
+                        notes.push(format!(
+                            "Not pulling: {} has no upstream branch, push it first",
+                            status.branch_label()
+                        ));
+                    } else if status.has_unpulled_commits() {
+                        let commit_first =
+                            status.is_dirty() && config.on_dirty() == OnDirty::Commit;
+                        // Committing first makes a branch that's behind diverge
+                        let local = status.ahead + usize::from(commit_first);
+                        let strategy = if local > 0 {
+                            config.diverged_pull_strategy()
+                        } else {
+                            Some(config.pull_strategy())
+                        };
+                        // rebase-autostash takes care of uncommitted changes by itself
+                        let autostashes = strategy.is_some_and(|s| s.autostashes());
+                        let blocker = commit_first
+                            .then(|| commit_blocker(&status, &staging, interactive))
+                            .flatten();
+                        if status.has_tracked_changes()
+                            && config.on_dirty() == OnDirty::Skip
+                            && !autostashes
+                        {
+                            notes.push(
+                                "Not pulling: there are uncommitted changes. Set on_dirty to autostash or commit to pull anyway"
+                                    .to_string(),
+                            );
+                        } else if let Some(why) = blocker {
+                            notes.push(format!(
+                                "Not pulling: local changes need committing first, but {why}"
+                            ));
+                        } else if commit_first && staging.leaves_changes() && !autostashes {
+                            notes.push(
+                                "Not pulling: some files are left out of the commit, and they'd be in the way. Set pull_strategy to rebase-autostash to pull anyway"
+                                    .to_string(),
+                            );
+                        } else if local > 0 && config.diverged_pull_strategy().is_none() {
+                            notes.push(diverged_note(&status, "pulling"));
+                        } else {
+                            if commit_first {
+                                plan_commit(&staging, &mut actions, &mut notes);
+                            }
+                            actions.push(if local > 0 {
+                                ActionStep::Reconcile
+                            } else {
+                                ActionStep::Pull
+                            });
+                        }
+                    }
+                }
+            }
+
+            plan_other_branches(&status, mode, &mut actions, &mut notes);
+
+            // Committing a secret or a huge file is worse than not syncing
+            // at all: it would be pushed, and stay in the history for good
+            if actions.contains(&ActionStep::Commit)
+                && let Some(note) = guard_note(&status, &staging)
+            {
+                actions.clear();
+                notes.push(note);
+            }
+
+            // Should a pull or a rebase go sideways, what was there before
+            // can still be recovered
+            let risky = actions.iter().any(|action| {
+                matches!(
+                    action,
+                    ActionStep::Stage
+                        | ActionStep::Commit
+                        | ActionStep::Pull
+                        | ActionStep::Reconcile
+                )
+            });
+            if risky && config.keep_backups() > 0 {
+                actions.insert(0, ActionStep::Backup);
+            }
+        }
+
+        let committing = actions.contains(&ActionStep::Commit);
+        let steps = actions
+            .into_iter()
+            .map(|action| PlannedStep::new(action, &status, committing))
+            .collect();
+        RepoPlan {
+            status,
+            steps,
+            notes,
+        }
+    }
+}
+
+/// Plans `grit sync`: commit local changes and pull, then push, so that the
+/// push can't be rejected for lack of a pull. Without local commits, the pull
+/// comes first, a fast-forward, with the changes to commit stashed out of its
+/// way. Otherwise the commit comes first, and the pull reconciles. If
+/// upstream commits can't be pulled in, the repository is left alone
+/// entirely.
+fn plan_sync(
+    status: &RepoStatus,
+    staging: &StagingChoice,
+    interactive: bool,
+    actions: &mut Vec<ActionStep>,
+    notes: &mut Vec<String>,
+) {
+    let config = &status.config;
+    if let Some(fetch) = status.stale_fetch() {
+        notes.push(format!(
+            "Not syncing: fetching {} failed ({})",
+            fetch.remote, fetch.error
+        ));
+        return;
+    }
+    if !config.push {
+        notes.push("Not pushing: the config says push = false".to_string());
+    }
+
+    // Local changes get committed when they're going to be pushed, or when
+    // the config asks for it before pulling
+    let wants_commit = status.is_dirty()
+        && (config.push || (status.has_unpulled_commits() && config.on_dirty() == OnDirty::Commit));
+    let blocker = wants_commit
+        .then(|| commit_blocker(status, staging, interactive))
+        .flatten();
+    let committing = wants_commit && blocker.is_none();
+    if let Some(why) = blocker {
+        notes.push(format!("Not committing local changes: {why}"));
+    }
+    let local = status.ahead + usize::from(committing);
+
+    let behind = status.upstream.is_some() && status.has_unpulled_commits();
+    let pull_first = behind && status.ahead == 0;
+    if behind {
+        let strategy = if pull_first {
+            Some(config.pull_strategy())
+        } else {
+            config.diverged_pull_strategy()
+        };
+        // Pulling first stashes the changes to commit, but not the others
+        let in_the_way = status.has_tracked_changes()
+            && (!committing || (!pull_first && staging.leaves_changes()));
+        let autostashes =
+            strategy.is_some_and(|s| s.autostashes()) || config.on_dirty() == OnDirty::Autostash;
+        if in_the_way && !autostashes {
+            notes.push(
+                "Not syncing: there are uncommitted changes in the way of pulling. Set on_dirty to autostash or commit to pull anyway"
+                    .to_string(),
+            );
+            return;
+        }
+        if strategy.is_none() {
+            notes.push(diverged_note(status, "syncing"));
+            return;
+        }
+    }
+
+    if pull_first {
+        actions.push(ActionStep::Pull);
+    }
+    if committing {
+        plan_commit(staging, actions, notes);
+    }
+    if behind && !pull_first {
+        actions.push(ActionStep::Reconcile);
+    }
+    if config.push && local > 0 {
+        actions.push(ActionStep::Push);
+    }
+}
+
+/// Plans fast-forwarding and pushing the branches from `branches`, and
+/// pushing to the remotes from `push_remotes`, after the checked out branch
+/// is taken care of. Pulling only fast-forwards, pushing only pushes.
+fn plan_other_branches(
+    status: &RepoStatus,
+    mode: SyncMode,
+    actions: &mut Vec<ActionStep>,
+    notes: &mut Vec<String>,
+) {
+    let pulls = matches!(mode, SyncMode::Pull | SyncMode::Sync);
+    let pushes = matches!(mode, SyncMode::Push | SyncMode::Sync) && status.config.push;
+
+    for branch in &status.branches {
+        let name = &branch.name;
+        if !branch.exists {
+            notes.push(format!("Not syncing {name}: there's no such branch"));
+        } else if branch.upstream.is_none() {
+            notes.push(format!("Not syncing {name}: it has no upstream branch"));
+        } else if let Some(fetch) = branch_fetch_failed(status, branch) {
+            notes.push(format!(
+                "Not syncing {name}: fetching {} failed ({})",
+                fetch.remote, fetch.error
+            ));
+        } else if branch.ahead > 0 && branch.behind > 0 {
+            notes.push(format!(
+                "Not syncing {name}: diverged from {} ({} local and {} upstream commits), check it out to sort it out",
+                branch.upstream.as_deref().unwrap_or_default(),
+                branch.ahead,
+                branch.behind
+            ));
+        } else if let Some(worktree) = branch.checked_out_in.as_deref()
+            && pulls
+            && branch.behind > 0
+        {
+            notes.push(format!(
+                "Not fast-forwarding {name}: it's checked out in {}, pull there",
+                display_path(worktree)
+            ));
+        }
+    }
+    if pulls && fast_forwards(status).next().is_some() {
+        actions.push(ActionStep::FastForward);
+    }
+    if !pushes {
+        return;
+    }
+    if branch_pushes(status).next().is_some() {
+        actions.push(ActionStep::PushBranches);
+    }
+
+    for remote in &status.push_remotes {
+        let name = &remote.remote;
+        if !remote.exists {
+            notes.push(format!("Not pushing to {name}: there's no such remote"));
+        } else if let Some(fetch) = failed_fetch(status, name) {
+            notes.push(format!(
+                "Not pushing to {name}: fetching it failed ({})",
+                fetch.error
+            ));
+        } else {
+            for branch in remote.branches.iter().filter(|branch| branch.behind > 0) {
+                notes.push(format!(
+                    "Not pushing {} to {name}: it has {} commits we don't",
+                    branch.name, branch.behind
+                ));
+            }
+        }
+    }
+    let committing = actions.contains(&ActionStep::Commit);
+    if remote_pushes(status, committing).next().is_some() {
+        actions.push(ActionStep::PushRemotes);
+    }
+}
+
+/// Explains why local changes can't be committed, if they can't.
+fn commit_blocker(
+    status: &RepoStatus,
+    staging: &StagingChoice,
+    interactive: bool,
+) -> Option<String> {
+    let config = &status.config;
+    if !interactive && config.commit_mode().needs_terminal() {
+        return Some(no_human_note(config));
+    }
+    if !interactive && config.staging() == Staging::Pick {
+        return Some(
+            "staging is pick, which asks which files to stage, and stdin is not a terminal. Set staging to all to stage unattended"
+                .to_string(),
+        );
+    }
+    if let Some((path, pattern)) = staging.denied_staged.first() {
+        return Some(format!(
+            "{path} is staged, but matches the stage_deny pattern {pattern}. Unstage it with `git restore --staged {path}`"
+        ));
+    }
+    if staging.committed.is_empty() {
+        return Some("every changed file is left out of staging".to_string());
+    }
+    None
+}
+
+/// Explains which of the files about to be committed were flagged, if any
+/// were, unless `guard_allow` allows them.
+fn guard_note(status: &RepoStatus, staging: &StagingChoice) -> Option<String> {
+    let flagged: Vec<String> = staging
+        .committed
+        .iter()
+        .filter(|file| !file.flags.is_empty())
+        .filter(|file| staging::matching_pattern(&file.path, &status.config.guard_allow).is_none())
+        .map(|file| {
+            let flags: Vec<String> = file.flags.iter().map(ToString::to_string).collect();
+            format!("{} ({})", file.path, flags.join(", "))
+        })
+        .collect();
+    if flagged.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "Blocked: about to commit flagged files: {}. Allow them with guard_allow, keep them out with stage_deny, or commit by hand",
+        flagged.join(", ")
+    ))
+}
+
+/// Stages the chosen files and commits them, noting which files are left out.
+fn plan_commit(staging: &StagingChoice, actions: &mut Vec<ActionStep>, notes: &mut Vec<String>) {
+    if !staging.denied.is_empty() {
+        let denied: Vec<String> = staging
+            .denied
+            .iter()
+            .map(|(path, pattern)| format!("{path} ({pattern})"))
+            .collect();
+        notes.push(format!(
+            "Not staging files matching stage_deny: {}",
+            denied.join(", ")
+        ));
+    }
+    if !staging.left_out.is_empty() {
+        notes.push(format!(
+            "Not staging files left out when picking: {}",
+            staging.left_out.join(", ")
+        ));
+    }
+    if !staging.to_stage.is_empty() {
+        actions.push(ActionStep::Stage);
+    }
+    actions.push(ActionStep::Commit);
+}
+
+/// Explains why a commit can't be made unattended, and how to change that.
+fn no_human_note(config: &RepoConfig) -> String {
+    let how = match config.commit_mode() {
+        CommitMode::Editor => "opens an editor",
+        CommitMode::Prompt => "asks for a message",
+        CommitMode::Auto => unreachable!("auto commits don't need a terminal"),
+    };
+    format!(
+        "commit_mode is {}, which {how}, and stdin is not a terminal. Set commit_mode to auto to commit unattended",
+        config.commit_mode()
+    )
+}
+
+/// Explains why a diverged repository is left alone, or one that committing
+/// would make diverge, and how to change that.
+fn diverged_note(status: &RepoStatus, doing: &str) -> String {
+    let upstream = status.upstream.as_deref().unwrap_or_default();
+    let state = if status.ahead == 0 {
+        format!(
+            "committing would diverge from {upstream}, which has {} new commits",
+            status.behind
+        )
+    } else {
+        format!(
+            "diverged from {upstream} ({} local and {} upstream commits)",
+            status.ahead, status.behind
+        )
+    };
+    let why = match status.config.on_diverged {
+        Some(OnDiverged::Skip) => "on_diverged is skip".to_string(),
+        _ => format!(
+            "{} pulls can't reconcile diverged branches",
+            status.config.pull_strategy()
+        ),
+    };
+    format!(
+        "Not {doing}: {state}, and {why}. Set on_diverged to rebase or merge to do it automatically, or sort it out by hand"
+    )
+}
+
+impl ExecutionPlan {
+    pub(crate) fn is_noop(&self) -> bool {
+        self.repo_plans.iter().all(|plan| plan.steps.is_empty())
+    }
+
+    /// Whether every step only brings upstream commits in: backups, pulls
+    /// and fast-forwards, no commits or pushes. Such plans run without
+    /// asking when stdin is not a terminal.
+    pub(crate) fn only_pulls(&self) -> bool {
+        self.repo_plans
+            .iter()
+            .flat_map(|repo_plan| &repo_plan.steps)
+            .all(|step| {
+                matches!(
+                    step.action,
+                    ActionStep::Backup | ActionStep::Pull | ActionStep::FastForward
+                )
+            })
+    }
+
+    /// Executes every repository's steps, and reports what happened to each
+    /// repository that had steps or notes, in plan order.
+    ///
+    /// Repositories whose steps need the terminal (to review a diff or write
+    /// a commit message) go first, one at a time, while whoever gave consent
+    /// is still around. The others then run `jobs` at a time, with a live
+    /// view of how each one is doing. Worktrees of the same repository count
+    /// as one, running one after the other.
+    ///
+    /// A failing repository doesn't stop the others, unless `fail_fast` is
+    /// set: then the repositories that haven't started yet are reported as
+    /// skipped.
+    pub(crate) async fn execute(&self, fail_fast: bool, jobs: usize) -> Vec<RepoReport> {
+        let aborted = Cell::new(false);
+        let mut reports = Vec::new();
+
+        let (attended, unattended): (Vec<_>, Vec<_>) = self
+            .repo_plans
+            .iter()
+            .enumerate()
+            .filter(|(_, repo_plan)| !repo_plan.steps.is_empty())
+            .partition(|(_, repo_plan)| repo_plan.needs_terminal());
+
+        for (index, repo_plan) in attended {
+            let report = repo_plan.execute_reporting(&aborted, fail_fast, None).await;
+            reports.push((index, report));
+        }
+
+        let progress = RepoProgress::new(
+            unattended
+                .iter()
+                .map(|(_, repo_plan)| repo_plan.status.path.as_path()),
+        );
+        let bars: Vec<_> = unattended
+            .iter()
+            .map(|(_, repo_plan)| progress.add(&repo_plan.status.path))
+            .collect();
+        let groups = group_worktrees(
+            unattended
+                .into_iter()
+                .zip(bars)
+                .map(|((index, repo_plan), bar)| ((index, bar), repo_plan))
+                .collect(),
+        );
+        let concurrent: Vec<Vec<_>> = futures_util::stream::iter(groups)
+            .map(|group| {
+                let aborted = &aborted;
+                let progress = &progress;
+                async move {
+                    let mut reports = Vec::new();
+                    for ((index, bar), repo_plan) in group {
+                        let report = repo_plan
+                            .execute_reporting(aborted, fail_fast, Some((progress, &bar)))
+                            .await;
+                        reports.push((index, report));
+                    }
+                    reports
+                }
+            })
+            .buffer_unordered(jobs.max(1))
+            .collect()
+            .await;
+        reports.extend(concurrent.into_iter().flatten());
+
+        for (index, repo_plan) in self.repo_plans.iter().enumerate() {
+            if repo_plan.steps.is_empty() && !repo_plan.notes.is_empty() {
+                let reason = repo_plan.notes.join("; ");
+                let outcome = RepoOutcome::Skipped { reason };
+                reports.push((
+                    index,
+                    RepoReport::new(repo_plan.status.path.clone(), outcome),
+                ));
+            }
+        }
+
+        reports.sort_by_key(|(index, _)| *index);
+        reports.into_iter().map(|(_, report)| report).collect()
+    }
+}
+
+/// Groups the worktrees of each repository, in plan order. Worktrees share
+/// refs, and git doesn't like several of them updating refs at once: each
+/// group runs one after the other.
+fn group_worktrees<T>(plans: Vec<(T, &RepoPlan)>) -> Vec<Vec<(T, &RepoPlan)>> {
+    let mut groups: Vec<Vec<(T, &RepoPlan)>> = Vec::new();
+    for (item, repo_plan) in plans {
+        let common_dir = &repo_plan.status.common_dir;
+        match groups
+            .iter_mut()
+            .find(|group| group[0].1.status.common_dir == *common_dir)
+        {
+            Some(group) => group.push((item, repo_plan)),
+            None => groups.push(vec![(item, repo_plan)]),
+        }
+    }
+    groups
+}
+
+impl RepoPlan {
+    fn needs_terminal(&self) -> bool {
+        self.steps
+            .iter()
+            .flat_map(|step| &step.commands)
+            .any(|command| command.needs_terminal())
+    }
+
+    /// Runs the steps unless an earlier repository aborted the run, and turns
+    /// the result into a report, along with where HEAD pointed before and
+    /// after. Failures are printed as they happen, above the live view if
+    /// there's one.
+    async fn execute_reporting(
+        &self,
+        aborted: &Cell<bool>,
+        fail_fast: bool,
+        progress: Option<(&RepoProgress, &ProgressBar)>,
+    ) -> RepoReport {
+        let path = self.status.path.clone();
+        if aborted.get() {
+            if let Some((_, bar)) = progress {
+                progress::finish_skipped(bar);
+            }
+            let reason = "an earlier repository failed, and --fail-fast was passed".to_string();
+            return RepoReport::new(path, RepoOutcome::Skipped { reason });
+        }
+
+        let before = self.snapshot().await;
+        let outcome = self.execute_outcome(fail_fast, aborted, progress).await;
+        let after = self.snapshot().await;
+        let tracked_changes = git::tracked_changes(&path).await.unwrap_or_default();
+        let branches = before
+            .branches
+            .into_iter()
+            .zip(after.branches)
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((branch, old), (_, new))| BranchMove { branch, old, new })
+            .collect();
+        RepoReport {
+            path,
+            outcome,
+            old_head: before.head,
+            new_head: after.head,
+            branches,
+            tracked_changes,
+        }
+    }
+
+    /// Where HEAD and the branches from `branches` point. Errors count as
+    /// nothing to point to: the snapshot is only there to be reported.
+    async fn snapshot(&self) -> RefSnapshot {
+        let path = self.status.path.as_path();
+        let head = git::resolve(path, "HEAD").await.ok().flatten();
+        let mut branches = Vec::new();
+        for branch in self.status.branches.iter().filter(|branch| branch.exists) {
+            let refname = format!("refs/heads/{}", branch.name);
+            let commit = git::resolve(path, &refname).await.ok().flatten();
+            branches.push((branch.name.clone(), commit));
+        }
+        RefSnapshot { head, branches }
+    }
+
+    async fn execute_outcome(
+        &self,
+        fail_fast: bool,
+        aborted: &Cell<bool>,
+        progress: Option<(&RepoProgress, &ProgressBar)>,
+    ) -> RepoOutcome {
+        match self.execute(progress.map(|(_, bar)| bar)).await {
+            Ok(()) => {
+                if let Some((_, bar)) = progress {
+                    progress::finish_succeeded(bar);
+                }
+                RepoOutcome::Succeeded
+            }
+            Err((step, error)) => {
+                if fail_fast {
+                    aborted.set(true);
+                }
+                let print_error = || {
+                    eprintln!(
+                        "{} Failed to {} {}: {:#}",
+                        "❌".red(),
+                        step.name().bright_yellow(),
+                        display_path(&self.status.path).bright_cyan(),
+                        error
+                    )
+                };
+                match progress {
+                    Some((progress, bar)) => {
+                        progress::finish_failed(bar, step.name());
+                        progress.suspend(print_error);
+                    }
+                    None => print_error(),
+                }
+                RepoOutcome::Failed {
+                    step: step.name().to_string(),
+                    error: output::strip_ansi(&format!("{error:#}")),
+                }
+            }
+        }
+    }
+
+    /// Runs the steps in order, returning the step that failed along with its
+    /// error. With a progress bar, commands run quietly and the bar shows
+    /// which one is running; without one, their output is echoed.
+    async fn execute(&self, bar: Option<&ProgressBar>) -> Result<(), (ActionStep, eyre::Report)> {
+        for step in &self.steps {
+            for command in &step.commands {
+                let result = match bar {
+                    Some(bar) => {
+                        progress::set_running(bar, &format!("git {}", command.args.join(" ")));
+                        command.run_quiet().await
+                    }
+                    None => command.run().await,
+                };
+                result.map_err(|error| (step.action, error))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ExecutionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "\n{} Plan:",
+            match self.mode {
+                SyncMode::Pull => "Pull",
+                SyncMode::Push => "Push",
+                SyncMode::Sync => "Sync",
+            }
+            .bright_cyan()
+        )?;
+
+        for repo_plan in &self.repo_plans {
+            let status = &repo_plan.status;
+            let display_path = display_path(&status.path);
+            let emoji = if status.stale_fetch().is_some() {
+                "🔌"
+            } else if status.has_diverged() {
+                "🔀"
+            } else if status.has_unstaged_changes {
+                "🔄"
+            } else if status.has_staged_changes {
+                "📦"
+            } else if status.has_unpushed_commits() {
+                "⬆️"
+            } else if status.has_unpulled_commits() {
+                "⬇️"
+            } else if status.has_other_branches_to_sync() {
+                "🌿"
+            } else {
+                "✅"
+            };
+
+            writeln!(
+                f,
+                "{} {} {} @ {}",
+                emoji,
+                display_path.bright_cyan(),
+                status.branch_label().bright_green(),
+                status.remote_label()
+            )?;
+
+            let mut actions = Vec::new();
+            if status.has_unstaged_changes {
+                actions.push("Needs staging".style(Style::new().bright_red()).to_string());
+            }
+            if status.has_staged_changes {
+                actions.push(
+                    "Needs commit"
+                        .style(Style::new().bright_yellow())
+                        .to_string(),
+                );
+            }
+            if status.has_unpushed_commits() && status.upstream.is_none() {
+                actions.push(
+                    format!("Needs push ({} new, no upstream yet)", status.ahead)
+                        .style(Style::new().bright_blue())
+                        .to_string(),
+                );
+            } else if status.has_unpushed_commits() && !status.has_diverged() {
+                actions.push(
+                    format!("Needs push ({} ahead)", status.ahead)
+                        .style(Style::new().bright_blue())
+                        .to_string(),
+                );
+            }
+            if status.has_diverged() {
+                actions.push(
+                    format!(
+                        "Diverged ({} ahead, {} behind)",
+                        status.ahead, status.behind
+                    )
+                    .style(Style::new().bright_red())
+                    .to_string(),
+                );
+            } else if status.has_unpulled_commits() {
+                actions.push(
+                    format!(
+                        "Needs pull ({} behind, {})",
+                        status.behind,
+                        status.config.pull_strategy()
+                    )
+                    .style(Style::new().bright_magenta())
+                    .to_string(),
+                );
+            }
+
+            if status.has_other_branches_to_sync() {
+                actions.push(
+                    "Other branches need syncing"
+                        .style(Style::new().bright_magenta())
+                        .to_string(),
+                );
+            }
+
+            if !actions.is_empty() {
+                for (i, action) in actions.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", action)?;
+                }
+                writeln!(f)?;
+            }
+
+            for step in &repo_plan.steps {
+                if step.action == ActionStep::Stage
+                    && self.picking_deferred
+                    && status.config.staging() == Staging::Pick
+                {
+                    writeln!(
+                        f,
+                        "  {}: {}",
+                        "Will ask which files to stage".bright_blue(),
+                        StagingChoice::new(status).to_stage.join(", ").bright_cyan()
+                    )?;
+                    continue;
+                }
+                for command in &step.commands {
+                    writeln!(f, "  {}: {}", "Will execute".bright_blue(), command)?;
+                }
+            }
+
+            for note in &repo_plan.notes {
+                writeln!(f, "  {} {}", "⚠️".yellow(), note.yellow())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::branches::{PushRemoteStatus, RemoteBranch, UpstreamRef};
+    use crate::git::InvocationMode;
+    use crate::guard::Flag;
+    use crate::status::ChangedFile;
+
+    fn status(branch: Option<&str>, upstream: Option<&str>, ahead: usize) -> RepoStatus {
+        RepoStatus {
+            config: RepoConfig::from_path(Utf8PathBuf::from("/repo")),
+            path: Utf8PathBuf::from("/repo"),
+            common_dir: Utf8PathBuf::from("/repo/.git"),
+            worktree_of: None,
+            branch: branch.map(str::to_string),
+            remote_name: "origin".to_string(),
+            remote: Some("git@example.com:repo.git".to_string()),
+            upstream: upstream.map(str::to_string),
+            upstream_remote: upstream
+                .and_then(|upstream| upstream.split_once('/'))
+                .map(|(remote, _)| remote.to_string()),
+            has_unstaged_changes: false,
+            has_staged_changes: false,
+            changed_files: Vec::new(),
+            left_out: Vec::new(),
+            ahead,
+            behind: 0,
+            failed_fetches: Vec::new(),
+            branches: Vec::new(),
+            push_remotes: Vec::new(),
+            submodules: Vec::new(),
+            backups: Vec::new(),
+        }
+    }
+
+    /// Adds a changed file, with its `git status --porcelain` letters
+    fn change(status: &mut RepoStatus, letters: &str, path: &str) {
+        let file = ChangedFile {
+            path: path.to_string(),
+            status: letters.to_string(),
+            flags: Vec::new(),
+        };
+        status.has_staged_changes |= file.is_staged();
+        status.has_unstaged_changes |= file.is_unstaged();
+        status.changed_files.push(file);
+    }
+
+    /// The arguments of every command but the backup's, whose ref is named
+    /// after the current time: `test_backups` looks at those
+    fn args(plan: &RepoPlan) -> Vec<Vec<String>> {
+        plan.steps
+            .iter()
+            .filter(|step| step.action != ActionStep::Backup)
+            .flat_map(|step| step.commands.iter().map(|c| c.args.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_new_branch_is_pushed_with_upstream() {
+        let plan = RepoPlan::new(status(Some("feature"), None, 2), SyncMode::Push, true);
+        assert_eq!(args(&plan), vec![vec!["push", "-u", "origin", "feature"]]);
+
+        let plan = RepoPlan::new(status(Some("feature"), None, 2), SyncMode::Pull, true);
+        assert!(plan.steps.is_empty());
+        assert_eq!(plan.notes.len(), 1);
+    }
+
+    #[test]
+    fn test_detached_head_and_missing_remote_are_skipped() {
+        let plan = RepoPlan::new(status(None, None, 0), SyncMode::Push, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("detached"));
+
+        let mut no_remote = status(Some("main"), None, 1);
+        no_remote.remote = None;
+        let plan = RepoPlan::new(no_remote, SyncMode::Push, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("no remote named origin"));
+    }
+
+    #[test]
+    fn test_pull_strategy() {
+        let mut behind = status(Some("main"), Some("origin/main"), 0);
+        behind.behind = 2;
+        let plan = RepoPlan::new(behind, SyncMode::Pull, true);
+        assert_eq!(args(&plan), vec![vec!["pull", "--ff-only"]]);
+
+        // ff-only can't reconcile diverged branches, so it's not even attempted
+        let mut diverged = status(Some("main"), Some("origin/main"), 1);
+        diverged.behind = 2;
+        let plan = RepoPlan::new(diverged, SyncMode::Pull, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("diverged from origin/main"));
+
+        let mut diverged = status(Some("main"), Some("origin/main"), 1);
+        diverged.behind = 2;
+        diverged.config.pull_strategy = Some(PullStrategy::Rebase);
+        let plan = RepoPlan::new(diverged, SyncMode::Pull, true);
+        assert_eq!(args(&plan), vec![vec!["pull", "--rebase"]]);
+    }
+
+    #[test]
+    fn test_diverged_push() {
+        // A plain push would be rejected, so it's not attempted
+        let mut diverged = status(Some("main"), Some("origin/main"), 1);
+        diverged.behind = 2;
+        let plan = RepoPlan::new(diverged, SyncMode::Push, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].starts_with("Not pushing: diverged from origin/main"));
+
+        // Committing would only make it diverge
+        let mut behind = status(Some("main"), Some("origin/main"), 0);
+        behind.behind = 2;
+        change(&mut behind, " M", "notes.md");
+        let plan = RepoPlan::new(behind, SyncMode::Push, true);
+        assert!(plan.steps.is_empty());
+        assert!(
+            plan.notes[0].starts_with("Not pushing: committing would diverge from origin/main")
+        );
+
+        let mut diverged = status(Some("main"), Some("origin/main"), 1);
+        diverged.behind = 2;
+        diverged.config.on_diverged = Some(OnDiverged::Rebase);
+        let plan = RepoPlan::new(diverged, SyncMode::Push, true);
+        let actions: Vec<_> = plan.steps.iter().map(|step| step.action).collect();
+        assert_eq!(
+            actions,
+            [ActionStep::Backup, ActionStep::Reconcile, ActionStep::Push]
+        );
+        assert_eq!(args(&plan), vec![vec!["pull", "--rebase"], vec!["push"]]);
+    }
+
+    #[test]
+    fn test_dirty_pull() {
+        let dirty = || {
+            let mut dirty = status(Some("main"), Some("origin/main"), 0);
+            dirty.behind = 2;
+            change(&mut dirty, " M", "notes.md");
+            dirty
+        };
+
+        let plan = RepoPlan::new(dirty(), SyncMode::Pull, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("uncommitted changes"));
+
+        // Untracked files alone aren't in the way
+        let mut untracked = status(Some("main"), Some("origin/main"), 0);
+        untracked.behind = 2;
+        change(&mut untracked, "??", "scratch.txt");
+        let plan = RepoPlan::new(untracked, SyncMode::Pull, true);
+        assert_eq!(args(&plan), vec![vec!["pull", "--ff-only"]]);
+
+        let mut autostash = dirty();
+        autostash.config.on_dirty = Some(OnDirty::Autostash);
+        let plan = RepoPlan::new(autostash, SyncMode::Pull, true);
+        assert_eq!(args(&plan), vec![vec!["pull", "--ff-only", "--autostash"]]);
+
+        // The commit makes the branch diverge, which ff-only can't handle
+        let mut commit = dirty();
+        commit.config.on_dirty = Some(OnDirty::Commit);
+        let plan = RepoPlan::new(commit, SyncMode::Pull, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("committing would diverge from origin/main"));
+
+        let mut commit = dirty();
+        commit.config.on_dirty = Some(OnDirty::Commit);
+        commit.config.on_diverged = Some(OnDiverged::Rebase);
+        commit.config.commit_mode = Some(CommitMode::Auto);
+        commit.config.commit_message = Some("sync".to_string());
+        let plan = RepoPlan::new(commit, SyncMode::Pull, false);
+        assert_eq!(
+            args(&plan),
+            vec![
+                vec!["--literal-pathspecs", "add", "-A", "--", "notes.md"],
+                vec!["commit", "-m", "sync"],
+                vec!["pull", "--rebase"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commit_modes() {
+        let dirty = |mode| {
+            let mut dirty = status(Some("main"), Some("origin/main"), 0);
+            change(&mut dirty, "M ", "notes.md");
+            dirty.config.commit_mode = Some(mode);
+            dirty
+        };
+
+        let plan = RepoPlan::new(dirty(CommitMode::Prompt), SyncMode::Push, true);
+        assert_eq!(plan.steps[1].action, ActionStep::Commit);
+        let modes: Vec<_> = plan.steps[1].commands.iter().map(|c| c.mode).collect();
+        assert_eq!(modes, [InvocationMode::Captured, InvocationMode::Prompt]);
+
+        // Only auto commits can happen without a human around
+        let plan = RepoPlan::new(dirty(CommitMode::Prompt), SyncMode::Push, false);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("commit_mode is prompt"));
+
+        let mut auto = dirty(CommitMode::Auto);
+        change(&mut auto, "A ", "todo.md");
+        auto.config.commit_message = Some("{n} files".to_string());
+        let plan = RepoPlan::new(auto, SyncMode::Push, false);
+        assert_eq!(
+            args(&plan),
+            vec![vec!["commit", "-m", "2 files"], vec!["push"]]
+        );
+    }
+
+    #[test]
+    fn test_stage_deny() {
+        let mut dirty = status(Some("main"), Some("origin/main"), 0);
+        change(&mut dirty, " M", "notes.md");
+        change(&mut dirty, "??", "config/.env");
+        change(&mut dirty, "??", "*");
+        dirty.config.stage_deny = vec![".env".to_string()];
+        let plan = RepoPlan::new(dirty, SyncMode::Push, true);
+        assert_eq!(
+            args(&plan)[0],
+            ["--literal-pathspecs", "add", "-A", "--", "notes.md", "*"]
+        );
+        assert!(plan.notes[0].contains("config/.env (.env)"));
+
+        // Already staged, so committing would leak it
+        let mut staged = status(Some("main"), Some("origin/main"), 0);
+        change(&mut staged, "A ", ".env");
+        staged.config.stage_deny = vec![".env".to_string()];
+        let plan = RepoPlan::new(staged, SyncMode::Push, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains(".env is staged"));
+    }
+
+    #[test]
+    fn test_deferred_picking() {
+        let mut dirty = status(Some("main"), Some("origin/main"), 0);
+        change(&mut dirty, " M", "notes.md");
+        change(&mut dirty, "??", "draft.md");
+        dirty.config.staging = Some(Staging::Pick);
+        let mut plan = ExecutionPlan::new(vec![dirty], SyncMode::Push, true);
+        assert!(plan.to_string().contains("--literal-pathspecs"));
+
+        // None of them are listed as staged before they're picked
+        plan.defer_picking();
+        let shown = plan.to_string();
+        assert!(shown.contains("Will ask which files to stage"));
+        assert!(shown.contains("notes.md, draft.md"));
+        assert!(!shown.contains("--literal-pathspecs"));
+    }
+
+    #[test]
+    fn test_flagged_files_block_the_repo() {
+        let flagged = || {
+            let mut flagged = status(Some("main"), Some("origin/main"), 1);
+            change(&mut flagged, " M", "notes.md");
+            change(&mut flagged, "??", "keys.txt");
+            flagged.changed_files[1].flags.push(Flag::Secret {
+                what: "an AWS access key",
+            });
+            flagged
+        };
+
+        // Not even the commits that are already there get pushed
+        let plan = RepoPlan::new(flagged(), SyncMode::Push, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("keys.txt (looks like an AWS access key)"));
+
+        let mut allowed = flagged();
+        allowed.config.guard_allow = vec!["keys.txt".to_string()];
+        let plan = RepoPlan::new(allowed, SyncMode::Push, true);
+        assert_eq!(plan.steps.len(), 4);
+
+        // Flagged files that aren't committed don't matter
+        let mut denied = flagged();
+        denied.config.stage_deny = vec!["keys.txt".to_string()];
+        let plan = RepoPlan::new(denied, SyncMode::Push, true);
+        assert_eq!(plan.steps.len(), 4);
+    }
+
+    #[test]
+    fn test_sync() {
+        // Nothing local to reconcile yet: fast-forward with the changes
+        // stashed, then commit and push them
+        let mut both = status(Some("main"), Some("origin/main"), 0);
+        both.behind = 1;
+        change(&mut both, "M ", "notes.md");
+        let plan = RepoPlan::new(both, SyncMode::Sync, true);
+        let actions: Vec<_> = plan.steps.iter().map(|step| step.action).collect();
+        assert_eq!(
+            actions,
+            [
+                ActionStep::Backup,
+                ActionStep::Pull,
+                ActionStep::Commit,
+                ActionStep::Push
+            ]
+        );
+        assert_eq!(
+            plan.steps[1].commands[0].args,
+            ["pull", "--ff-only", "--autostash"]
+        );
+
+        // Untracked files alone don't need stashing
+        let mut untracked = status(Some("main"), Some("origin/main"), 0);
+        untracked.behind = 1;
+        change(&mut untracked, "??", "notes.md");
+        let plan = RepoPlan::new(untracked, SyncMode::Sync, true);
+        assert_eq!(plan.steps[1].commands[0].args, ["pull", "--ff-only"]);
+        assert_eq!(plan.steps.last().unwrap().action, ActionStep::Push);
+
+        // With a local commit, commit, pull in the upstream commits, then
+        // push the result
+        let mut diverged = status(Some("main"), Some("origin/main"), 1);
+        diverged.behind = 1;
+        change(&mut diverged, "M ", "notes.md");
+        diverged.config.on_diverged = Some(OnDiverged::Rebase);
+        let plan = RepoPlan::new(diverged, SyncMode::Sync, true);
+        let actions: Vec<_> = plan.steps.iter().map(|step| step.action).collect();
+        assert_eq!(
+            actions,
+            [
+                ActionStep::Backup,
+                ActionStep::Commit,
+                ActionStep::Reconcile,
+                ActionStep::Push
+            ]
+        );
+
+        // Nothing local: just a fast-forward
+        let mut behind = status(Some("main"), Some("origin/main"), 0);
+        behind.behind = 3;
+        let plan = RepoPlan::new(behind, SyncMode::Sync, true);
+        assert_eq!(args(&plan), vec![vec!["pull", "--ff-only"]]);
+
+        // Can't pull, so nothing is done at all, not even the commit
+        let mut blocked = status(Some("main"), Some("origin/main"), 1);
+        blocked.behind = 1;
+        change(&mut blocked, "??", "notes.md");
+        let plan = RepoPlan::new(blocked, SyncMode::Sync, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].starts_with("Not syncing: diverged from origin/main (1 local"));
+    }
+
+    #[test]
+    fn test_other_branches_and_remotes() {
+        let branch = |name: &str, ahead, behind| BranchStatus {
+            name: name.to_string(),
+            exists: true,
+            upstream: Some(format!("origin/{name}")),
+            upstream_ref: Some(UpstreamRef {
+                local_ref: format!("refs/remotes/origin/{name}"),
+                remote: "origin".to_string(),
+                remote_branch: name.to_string(),
+            }),
+            ahead,
+            behind,
+            checked_out_in: None,
+        };
+        let mut repo = status(Some("main"), Some("origin/main"), 0);
+        repo.branches = vec![
+            branch("release", 0, 2),
+            branch("drafts", 1, 0),
+            branch("old", 1, 1),
+        ];
+        repo.push_remotes = vec![PushRemoteStatus {
+            remote: "mirror".to_string(),
+            exists: true,
+            branches: vec![
+                RemoteBranch {
+                    name: "main".to_string(),
+                    ahead: 0,
+                    behind: 0,
+                },
+                RemoteBranch {
+                    name: "drafts".to_string(),
+                    ahead: 3,
+                    behind: 0,
+                },
+            ],
+        }];
+
+        let plan = RepoPlan::new(repo.clone(), SyncMode::Pull, true);
+        assert_eq!(
+            args(&plan),
+            vec![vec![
+                "fetch",
+                ".",
+                "refs/remotes/origin/release:refs/heads/release"
+            ]]
+        );
+        assert!(plan.notes[0].starts_with("Not syncing old: diverged"));
+
+        // Diverged branches and remotes can't be synced, so they don't count
+        let mut stuck = status(Some("main"), Some("origin/main"), 0);
+        stuck.branches = vec![branch("old", 1, 1)];
+        stuck.push_remotes = vec![PushRemoteStatus {
+            remote: "mirror".to_string(),
+            exists: true,
+            branches: vec![RemoteBranch {
+                name: "main".to_string(),
+                ahead: 1,
+                behind: 1,
+            }],
+        }];
+        assert!(!stuck.has_other_branches_to_sync());
+        assert!(repo.has_other_branches_to_sync());
+
+        let plan = RepoPlan::new(repo.clone(), SyncMode::Push, true);
+        assert_eq!(
+            args(&plan),
+            vec![
+                vec!["push", "origin", "drafts"],
+                vec!["push", "mirror", "drafts"]
+            ]
+        );
+
+        // The checked out branch goes to the mirror too once it's committed
+        change(&mut repo, "M ", "notes.md");
+        repo.config.commit_mode = Some(CommitMode::Auto);
+        let plan = RepoPlan::new(repo, SyncMode::Sync, true);
+        let actions: Vec<_> = plan.steps.iter().map(|step| step.action).collect();
+        assert_eq!(
+            actions,
+            [
+                ActionStep::Backup,
+                ActionStep::Commit,
+                ActionStep::Push,
+                ActionStep::FastForward,
+                ActionStep::PushBranches,
+                ActionStep::PushRemotes
+            ]
+        );
+        assert_eq!(
+            plan.steps[5].commands[0].args,
+            ["push", "mirror", "main", "drafts"]
+        );
+    }
+
+    #[test]
+    fn test_backups() {
+        let backup = |time: &str, commit: &str| backups::Backup {
+            path: Utf8PathBuf::from("/repo"),
+            refname: format!("refs/grit/backup/{time}/feature/login"),
+            time: Timestamp::now(),
+            branch: "feature/login".to_string(),
+            commit: commit.to_string(),
+            with_changes: false,
+        };
+        let mut behind = status(Some("feature/login"), Some("origin/feature/login"), 0);
+        behind.behind = 2;
+        behind.config.keep_backups = Some(2);
+        behind.backups = vec![
+            backup("20261017T090000Z", "3c3c3c3c"),
+            backup("20261016T090000Z", "2b2b2b2b"),
+            backup("20261015T090000Z", "1a1a1a1a"),
+        ];
+        let plan = RepoPlan::new(behind, SyncMode::Pull, true);
+        let actions: Vec<_> = plan.steps.iter().map(|step| step.action).collect();
+        assert_eq!(actions, [ActionStep::Backup, ActionStep::Pull]);
+        let commands = &plan.steps[0].commands;
+        assert_eq!(commands[0].mode, InvocationMode::Snapshot);
+        assert!(commands[0].args[3].starts_with("refs/grit/backup/"));
+        assert!(commands[0].args[3].ends_with("Z/feature/login"));
+        assert_eq!(commands[0].args[4..], [git::SNAPSHOT_ARG, ""]);
+        // With the new one, that's as many as `keep_backups` allows
+        let pruned: Vec<_> = commands[1..].iter().map(|c| c.args.clone()).collect();
+        assert_eq!(
+            pruned,
+            [
+                [
+                    "update-ref",
+                    "-d",
+                    "refs/grit/backup/20261016T090000Z/feature/login",
+                    "2b2b2b2b"
+                ],
+                [
+                    "update-ref",
+                    "-d",
+                    "refs/grit/backup/20261015T090000Z/feature/login",
+                    "1a1a1a1a"
+                ]
+            ]
+        );
+
+        // Pushing can't lose anything
+        let plan = RepoPlan::new(
+            status(Some("main"), Some("origin/main"), 1),
+            SyncMode::Push,
+            true,
+        );
+        assert_eq!(plan.steps.len(), 1);
+        assert_eq!(args(&plan), vec![vec!["push"]]);
+
+        // Committing before pulling can. Backing up never needs the terminal,
+        // even when committing does
+        let mut commit = status(Some("main"), Some("origin/main"), 0);
+        commit.behind = 2;
+        change(&mut commit, " M", "notes.md");
+        commit.config.on_dirty = Some(OnDirty::Commit);
+        commit.config.on_diverged = Some(OnDiverged::Rebase);
+        commit.config.commit_mode = Some(CommitMode::Prompt);
+        let plan = RepoPlan::new(commit, SyncMode::Pull, true);
+        let actions: Vec<_> = plan.steps.iter().map(|step| step.action).collect();
+        assert_eq!(
+            actions,
+            [
+                ActionStep::Backup,
+                ActionStep::Stage,
+                ActionStep::Commit,
+                ActionStep::Reconcile
+            ]
+        );
+        assert!(
+            !plan.steps[0]
+                .commands
+                .iter()
+                .any(GitInvocation::needs_terminal)
+        );
+        assert!(
+            plan.steps[2]
+                .commands
+                .iter()
+                .any(GitInvocation::needs_terminal)
+        );
+
+        // Nothing to back up for when the guard leaves the repository alone
+        let mut flagged = status(Some("main"), Some("origin/main"), 0);
+        flagged.behind = 2;
+        change(&mut flagged, "??", "keys.txt");
+        flagged.changed_files[0].flags.push(Flag::Secret {
+            what: "an AWS access key",
+        });
+        flagged.config.on_diverged = Some(OnDiverged::Rebase);
+        let plan = RepoPlan::new(flagged, SyncMode::Sync, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("keys.txt"));
+
+        let mut off = status(Some("main"), Some("origin/main"), 0);
+        off.behind = 2;
+        off.config.keep_backups = Some(0);
+        let plan = RepoPlan::new(off, SyncMode::Pull, true);
+        assert_eq!(plan.steps.len(), 1);
+    }
+
+    #[test]
+    fn test_only_pulls() {
+        let plan = |mode, status| ExecutionPlan::new(vec![status], mode, false);
+
+        let mut behind = status(Some("main"), Some("origin/main"), 0);
+        behind.behind = 2;
+        behind.config.on_dirty = Some(OnDirty::Autostash);
+        change(&mut behind, " M", "notes.md");
+        assert!(plan(SyncMode::Pull, behind.clone()).only_pulls());
+
+        // Nothing to do at all
+        let clean = status(Some("main"), Some("origin/main"), 0);
+        assert!(plan(SyncMode::Sync, clean).only_pulls());
+
+        let ahead = status(Some("main"), Some("origin/main"), 1);
+        assert!(!plan(SyncMode::Push, ahead).only_pulls());
+
+        // Auto commits don't need a human, but aren't pulls either
+        let mut commit = behind.clone();
+        commit.config.on_dirty = Some(OnDirty::Commit);
+        commit.config.on_diverged = Some(OnDiverged::Rebase);
+        commit.config.commit_mode = Some(CommitMode::Auto);
+        commit.config.commit_message = Some("sync".to_string());
+        assert!(!plan(SyncMode::Pull, commit).only_pulls());
+
+        let mut diverged = status(Some("main"), Some("origin/main"), 1);
+        diverged.behind = 2;
+        diverged.config.on_diverged = Some(OnDiverged::Rebase);
+        assert!(!plan(SyncMode::Pull, diverged).only_pulls());
+    }
+
+    #[test]
+    fn test_group_worktrees() {
+        let plan = |path: &str, common_dir: &str| {
+            let mut status = status(Some("main"), Some("origin/main"), 1);
+            status.path = Utf8PathBuf::from(path);
+            status.common_dir = Utf8PathBuf::from(common_dir);
+            RepoPlan::new(status, SyncMode::Push, true)
+        };
+        let plans = [
+            plan("/repo", "/repo/.git"),
+            plan("/other", "/other/.git"),
+            plan("/repo-feature", "/repo/.git"),
+            plan("/srv/bare/main", "/srv/bare.git"),
+            plan("/srv/bare/dev", "/srv/bare.git"),
+        ];
+
+        let groups = group_worktrees(plans.iter().enumerate().collect());
+        let indices: Vec<Vec<usize>> = groups
+            .iter()
+            .map(|group| group.iter().map(|(index, _)| *index).collect())
+            .collect();
+        assert_eq!(indices, [vec![0, 2], vec![1], vec![3, 4]]);
+    }
+
+    #[test]
+    fn test_stale_repos_are_left_alone() {
+        let mut stale = status(Some("main"), Some("origin/main"), 1);
+        stale.behind = 2;
+        stale.failed_fetches.push(FailedFetch {
+            remote: "origin".to_string(),
+            error: "fatal: unable to access".to_string(),
+        });
+        let plan = RepoPlan::new(stale, SyncMode::Pull, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("fetching origin failed"));
+    }
+
+    #[test]
+    fn test_upstream_on_another_remote() {
+        // `git pull` would pull from origin, not from the configured remote
+        let mut elsewhere = status(Some("main"), Some("origin/main"), 1);
+        elsewhere.behind = 2;
+        elsewhere.remote_name = "mirror".to_string();
+        let plan = RepoPlan::new(elsewhere.clone(), SyncMode::Pull, true);
+        assert!(plan.steps.is_empty());
+        assert!(plan.notes[0].contains("main tracks origin/main, on origin"));
+        assert!(plan.notes[0].contains("--set-upstream-to mirror/main"));
+        let plan = RepoPlan::new(elsewhere, SyncMode::Push, true);
+        assert!(plan.steps.is_empty());
+    }
+}
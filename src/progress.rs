@@ -0,0 +1,93 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! A live view of repositories being worked on concurrently: one line per
+//! repository, with a spinner and what it's up to.
+//!
+//! When stderr isn't a terminal, nothing is drawn, and the results summary
+//! printed afterwards is all there is.
+
+use std::time::Duration;
+
+use camino::Utf8Path;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use owo_colors::OwoColorize;
+
+use crate::display_path;
+
+pub(crate) struct RepoProgress {
+    multi: MultiProgress,
+    path_width: usize,
+}
+
+impl RepoProgress {
+    /// Sets up the view for the given repositories, which are expected to
+    /// get a line each through [`RepoProgress::add`].
+    pub(crate) fn new<'a>(paths: impl Iterator<Item = &'a Utf8Path>) -> Self {
+        let path_width = paths
+            .map(|path| display_path(path).as_str().len())
+            .max()
+            .unwrap_or(0);
+        Self {
+            multi: MultiProgress::with_draw_target(ProgressDrawTarget::stderr()),
+            path_width,
+        }
+    }
+
+    /// Adds a line for a repository, waiting for its turn.
+    pub(crate) fn add(&self, path: &Utf8Path) -> ProgressBar {
+        let style = ProgressStyle::with_template("  {spinner} {prefix} {msg}")
+            .expect("progress template is valid")
+            .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏ ");
+        let bar = self.multi.add(ProgressBar::new_spinner().with_style(style));
+        bar.set_prefix(
+            format!("{:width$}", display_path(path), width = self.path_width)
+                .bright_cyan()
+                .to_string(),
+        );
+        bar.set_message("waiting".dimmed().to_string());
+        bar
+    }
+
+    /// Prints above the live lines without garbling them, e.g. for errors.
+    pub(crate) fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.multi.suspend(f)
+    }
+}
+
+/// How much of what's running is shown. Longer lines would wrap, which
+/// garbles the live view, e.g. when staging many files.
+const MAX_RUNNING_WIDTH: usize = 60;
+
+/// Marks a repository as being worked on, showing what's running.
+pub(crate) fn set_running(bar: &ProgressBar, what: &str) {
+    bar.enable_steady_tick(Duration::from_millis(100));
+    let what = match what.char_indices().nth(MAX_RUNNING_WIDTH) {
+        Some((end, _)) => format!("{}…", &what[..end]),
+        None => what.to_string(),
+    };
+    bar.set_message(what.bright_blue().to_string());
+}
+
+pub(crate) fn finish_succeeded(bar: &ProgressBar) {
+    bar.finish_with_message(format!("{} {}", "✅".green(), "done".green()));
+}
+
+pub(crate) fn finish_failed(bar: &ProgressBar, step: &str) {
+    bar.finish_with_message(format!(
+        "{} {} {}",
+        "❌".red(),
+        "failed to".bright_red(),
+        step.bright_yellow()
+    ));
+}
+
+pub(crate) fn finish_skipped(bar: &ProgressBar) {
+    bar.finish_with_message(format!("{} {}", "⏭️".yellow(), "skipped".yellow()));
+}
@@ -0,0 +1,71 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+use std::io::{self, IsTerminal, Write};
+
+use eyre::WrapErr;
+use owo_colors::OwoColorize;
+
+/// Whether there's a human on the other end of stdin. When there isn't (cron,
+/// systemd timers, CI), nothing may wait for input or open an editor.
+pub(crate) fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Reads a single line from stdin, without the trailing newline.
+pub(crate) fn read_line() -> eyre::Result<String> {
+    io::stderr().flush().wrap_err("Failed to flush stderr")?;
+
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .wrap_err("Failed to read input")?;
+    Ok(input.trim().to_string())
+}
+
+/// Asks for consent before applying a plan. Returns `true` if we may proceed.
+///
+/// With `assume_yes`, consent is given up front and nothing is read. Without
+/// it, stdin must be a terminal: we refuse to guess consent from a pipe,
+/// unless the plan is `unattended`: it only pulls, for cron jobs and CI to
+/// pull without passing `--yes`. Anything that commits or pushes needs it.
+pub(crate) fn ask_for_consent(assume_yes: bool, unattended: bool) -> eyre::Result<bool> {
+    if assume_yes {
+        eprintln!(
+            "\nProceeding without asking, since {} was passed.",
+            "--yes".bright_yellow()
+        );
+        return Ok(true);
+    }
+
+    if !is_interactive() {
+        if unattended {
+            eprintln!(
+                "\nProceeding without asking, since stdin is not a terminal and the plan only pulls."
+            );
+            return Ok(true);
+        }
+        return Err(eyre::eyre!(
+            "stdin is not a terminal, so grit can't ask for consent. Pass {} to apply the plan unattended.",
+            "--yes".bright_yellow()
+        ));
+    }
+
+    eprint!(
+        "\nDo you want to proceed? Type {} to continue: ",
+        "yes".green()
+    );
+    let input = read_line()?;
+
+    if input != "yes" {
+        eprintln!("{}", "Operation cancelled.".red());
+        return Ok(false);
+    }
+    Ok(true)
+}
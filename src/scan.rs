@@ -0,0 +1,226 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! `grit scan`: finds git repositories under a directory and offers to add
+//! the ones that aren't in the config yet.
+
+use std::fmt;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+
+use crate::config::RepoConfig;
+use crate::manage::{self, DiscoveredRepo};
+use crate::{display_path, output};
+
+/// Directories that are never worth descending into: they're huge, and any
+/// `.git` in there belongs to a dependency, not to us.
+const IGNORED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// Finds every git working tree under `root`, going at most `max_depth`
+/// directories deep (`root` itself being depth 0).
+///
+/// A directory counts as a working tree if it has a `.git` entry, which is a
+/// directory for regular clones and a file for worktrees. We don't descend
+/// into working trees: nested repositories are submodules or vendored code.
+/// Symlinks aren't followed, so loops can't happen.
+pub(crate) fn find_repos(root: &Utf8Path, max_depth: usize) -> eyre::Result<Vec<Utf8PathBuf>> {
+    let mut found = Vec::new();
+    let mut pending = vec![(root.to_owned(), 0)];
+
+    while let Some((dir, depth)) = pending.pop() {
+        if dir.join(".git").exists() {
+            found.push(dir);
+            continue;
+        }
+        if depth == max_depth {
+            continue;
+        }
+
+        let entries = match dir.read_dir_utf8() {
+            Ok(entries) => entries,
+            // The root must be readable, but a single unreadable directory
+            // deeper down shouldn't spoil the whole scan
+            Err(e) if depth > 0 => {
+                eprintln!(
+                    "{} Skipping {}: {}",
+                    "⚠️".yellow(),
+                    display_path(&dir).bright_cyan(),
+                    e.to_string().yellow()
+                );
+                continue;
+            }
+            Err(e) => return Err(eyre::eyre!("Failed to read {}: {e}", dir.red())),
+        };
+
+        for entry in entries {
+            // Like unreadable directories, an entry we can't tell the type of
+            // is skipped rather than spoiling the whole scan
+            let entry = match entry.and_then(|entry| Ok((entry.file_type()?, entry))) {
+                Ok((file_type, entry)) if file_type.is_dir() => entry,
+                Ok(_) => continue,
+                Err(e) => {
+                    eprintln!(
+                        "{} Skipping an entry of {}: {}",
+                        "⚠️".yellow(),
+                        display_path(&dir).bright_cyan(),
+                        e.to_string().yellow()
+                    );
+                    continue;
+                }
+            };
+            if IGNORED_DIRS.contains(&entry.file_name()) {
+                continue;
+            }
+            pending.push((entry.into_path(), depth + 1));
+        }
+    }
+
+    found.sort();
+    Ok(found)
+}
+
+pub(crate) struct ScanPlan {
+    pub(crate) root: Utf8PathBuf,
+    /// Repositories found that aren't in the config yet
+    pub(crate) new_repos: Vec<DiscoveredRepo>,
+    /// How many of the repositories found are configured already
+    pub(crate) already_configured: usize,
+    /// Directories that looked like repositories but that git refused, with the reason why
+    pub(crate) skipped: Vec<(Utf8PathBuf, String)>,
+}
+
+impl ScanPlan {
+    pub(crate) async fn new(
+        root: &Utf8Path,
+        max_depth: usize,
+        configured: &[RepoConfig],
+    ) -> eyre::Result<Self> {
+        let mut new_repos = Vec::new();
+        let mut already_configured = 0;
+        let mut skipped = Vec::new();
+
+        for path in find_repos(root, max_depth)? {
+            if configured.iter().any(|repo| repo.path == path) {
+                already_configured += 1;
+                continue;
+            }
+            match manage::inspect_repo(&path).await {
+                Ok(repo) => new_repos.push(repo),
+                Err(e) => skipped.push((path, output::strip_ansi(&format!("{e:#}")))),
+            }
+        }
+
+        Ok(Self {
+            root: root.to_owned(),
+            new_repos,
+            already_configured,
+            skipped,
+        })
+    }
+
+    pub(crate) fn is_noop(&self) -> bool {
+        self.new_repos.is_empty()
+    }
+
+    /// Adds every new repository to the config.
+    pub(crate) fn execute(&self, configured: &[RepoConfig]) -> eyre::Result<()> {
+        for repo in &self.new_repos {
+            manage::record_repo(repo, configured)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ScanPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "\n{} Plan for {}:",
+            "Scan".bright_cyan(),
+            display_path(&self.root).bright_cyan()
+        )?;
+
+        for repo in &self.new_repos {
+            writeln!(f, "➕ {}", display_path(&repo.path).bright_cyan())?;
+            match &repo.url {
+                Some(url) => writeln!(f, "  url: {}", crate::normalize_remote(url))?,
+                None => writeln!(f, "  {}", "no origin remote".yellow())?,
+            }
+        }
+
+        for (path, reason) in &self.skipped {
+            writeln!(
+                f,
+                "{} Skipping {}: {}",
+                "⚠️".yellow(),
+                display_path(path).bright_cyan(),
+                reason.yellow()
+            )?;
+        }
+
+        if self.already_configured > 0 {
+            writeln!(
+                f,
+                "{} {} repositories found are already in the config",
+                "ℹ️".bright_blue(),
+                self.already_configured.to_string().bright_yellow()
+            )?;
+        }
+        if self.new_repos.is_empty() {
+            writeln!(f, "✅ No new repositories to add")?;
+        } else {
+            writeln!(
+                f,
+                "\n{}: add {} repositories to the config",
+                "Will".bright_blue(),
+                self.new_repos.len().to_string().bright_yellow()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_repos() -> eyre::Result<()> {
+        let root = Utf8PathBuf::try_from(std::env::temp_dir())?
+            .join(format!("grit-scan-test-{}", fastrand::u64(..)));
+        for dir in [
+            "projects/app/.git",
+            "projects/app/vendor/dep/.git",
+            "projects/tool/target/build/.git",
+            "projects/web/node_modules/pkg/.git",
+            "projects/web/.git",
+            "deep/a/b/c/.git",
+        ] {
+            std::fs::create_dir_all(root.join(dir))?;
+        }
+        // Worktrees have a `.git` file pointing at the main repository
+        std::fs::create_dir_all(root.join("worktrees/feature"))?;
+        std::fs::write(
+            root.join("worktrees/feature/.git"),
+            "gitdir: /elsewhere/.git/worktrees/feature\n",
+        )?;
+
+        let found = find_repos(&root, 3)?;
+        std::fs::remove_dir_all(&root)?;
+
+        let found: Vec<_> = found
+            .iter()
+            .map(|path| path.strip_prefix(&root).unwrap().as_str())
+            .collect();
+        assert_eq!(found, ["projects/app", "projects/web", "worktrees/feature"]);
+        Ok(())
+    }
+}
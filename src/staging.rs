@@ -0,0 +1,209 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! Deciding which changed files get staged: everything, minus what matches a
+//! `stage_deny` pattern, minus what was left out when picking from a list.
+
+use owo_colors::OwoColorize;
+
+use crate::display_path;
+use crate::status::{ChangedFile, RepoStatus};
+
+/// What staging would do to a repository's changed files
+#[derive(Debug, Default)]
+pub(crate) struct StagingChoice<'a> {
+    /// Files to pass to `git add`
+    pub(crate) to_stage: Vec<&'a str>,
+    /// Unstaged files that match a `stage_deny` pattern, with the pattern
+    pub(crate) denied: Vec<(&'a str, &'a str)>,
+    /// Files that were already staged, but match a `stage_deny` pattern:
+    /// committing would include them anyway
+    pub(crate) denied_staged: Vec<(&'a str, &'a str)>,
+    /// Files that were picked to be left out
+    pub(crate) left_out: Vec<&'a str>,
+    /// The files the commit would contain
+    pub(crate) committed: Vec<&'a ChangedFile>,
+}
+
+impl<'a> StagingChoice<'a> {
+    pub(crate) fn new(status: &'a RepoStatus) -> Self {
+        let mut choice = StagingChoice::default();
+        for file in &status.changed_files {
+            let path = file.path.as_str();
+            if let Some(pattern) = matching_pattern(path, &status.config.stage_deny) {
+                if file.is_staged() {
+                    choice.denied_staged.push((path, pattern));
+                } else {
+                    choice.denied.push((path, pattern));
+                }
+            } else if status.left_out.iter().any(|left_out| left_out == path) {
+                // Whatever was staged by hand stays staged
+                if file.is_staged() {
+                    choice.committed.push(file);
+                }
+                choice.left_out.push(path);
+            } else {
+                if file.is_unstaged() {
+                    choice.to_stage.push(path);
+                }
+                choice.committed.push(file);
+            }
+        }
+        choice
+    }
+
+    /// Whether some changed files stay uncommitted after the commit
+    pub(crate) fn leaves_changes(&self) -> bool {
+        !self.denied.is_empty() || !self.left_out.is_empty()
+    }
+}
+
+/// The pattern that `path` matches, if any, for `stage_deny` and
+/// `guard_allow`.
+///
+/// Like in `.gitignore`, a pattern without a `/` matches a file or directory
+/// name anywhere, so `.env` catches `config/.env` and `node_modules` catches
+/// everything in it. A pattern with a `/` matches paths from the top of the
+/// working tree, or the directories they lead to.
+pub(crate) fn matching_pattern<'p>(path: &str, patterns: &'p [String]) -> Option<&'p str> {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    patterns
+        .iter()
+        .find(|pattern| {
+            let Ok(glob) = glob::Pattern::new(pattern.trim_start_matches('/')) else {
+                // Checked when reading the config
+                return false;
+            };
+            if pattern.contains('/') {
+                path.match_indices('/')
+                    .map(|(end, _)| &path[..end])
+                    .chain([path])
+                    .any(|prefix| glob.matches_with(prefix, options))
+            } else {
+                path.split('/').any(|name| glob.matches_with(name, options))
+            }
+        })
+        .map(String::as_str)
+}
+
+/// Lists a repository's stageable files and asks which ones to stage.
+/// Returns the paths of the files left out.
+pub(crate) fn ask_left_out(
+    status: &RepoStatus,
+    files: &[&ChangedFile],
+) -> eyre::Result<Vec<String>> {
+    eprintln!(
+        "\n📝 Changed files in {}:",
+        display_path(&status.path).bright_cyan()
+    );
+    for (index, file) in files.iter().enumerate() {
+        eprintln!(
+            "  {:>3}  {}  {}",
+            (index + 1).to_string().bright_yellow(),
+            file.status.bright_red(),
+            file.path
+        );
+    }
+
+    loop {
+        eprint!(
+            "Stage which files? e.g. {}, {} for all, {} for none: ",
+            "1 3-5".bright_yellow(),
+            "Enter".green(),
+            "none".red()
+        );
+        let input = crate::prompt::read_line()?;
+        match parse_selection(&input, files.len()) {
+            Ok(selected) => {
+                return Ok(files
+                    .iter()
+                    .zip(selected)
+                    .filter(|(_, selected)| !selected)
+                    .map(|(file, _)| file.path.clone())
+                    .collect());
+            }
+            Err(e) => eprintln!("{} {e}", "⚠️".yellow()),
+        }
+    }
+}
+
+/// Parses a selection like `1 3-5,7` into one flag per item. An empty
+/// selection means everything, `none` means nothing.
+fn parse_selection(input: &str, count: usize) -> eyre::Result<Vec<bool>> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(vec![true; count]);
+    }
+    let mut selected = vec![false; count];
+    if input == "none" {
+        return Ok(selected);
+    }
+
+    let parse = |number: &str| -> eyre::Result<usize> {
+        match number.trim().parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => Ok(n),
+            _ => Err(eyre::eyre!(
+                "{} isn't a number between 1 and {count}",
+                number.trim().red()
+            )),
+        }
+    };
+    for part in input.split([' ', ',']).filter(|part| !part.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(part)?, parse(part)?),
+        };
+        if start > end {
+            return Err(eyre::eyre!("{} is an empty range", part.red()));
+        }
+        for flag in selected.iter_mut().take(end).skip(start - 1) {
+            *flag = true;
+        }
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching_pattern() {
+        let patterns = vec![
+            ".env".to_string(),
+            "*.swp".to_string(),
+            "build/out".to_string(),
+        ];
+        assert_eq!(matching_pattern("config/.env", &patterns), Some(".env"));
+        assert_eq!(
+            matching_pattern("src/.main.rs.swp", &patterns),
+            Some("*.swp")
+        );
+        assert_eq!(
+            matching_pattern("build/out/app.bin", &patterns),
+            Some("build/out")
+        );
+        assert_eq!(matching_pattern("src/build/out", &patterns), None);
+        assert_eq!(matching_pattern("src/main.rs", &patterns), None);
+    }
+
+    #[test]
+    fn test_parse_selection() -> eyre::Result<()> {
+        assert_eq!(parse_selection("", 3)?, [true, true, true]);
+        assert_eq!(parse_selection("none", 3)?, [false, false, false]);
+        assert_eq!(parse_selection("1 3", 3)?, [true, false, true]);
+        assert_eq!(parse_selection("2-3,1", 4)?, [true, true, true, false]);
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("one", 3).is_err());
+        Ok(())
+    }
+}
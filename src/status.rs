@@ -0,0 +1,816 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+use camino::{Utf8Path, Utf8PathBuf};
+use eyre::WrapErr;
+use futures_util::StreamExt;
+use owo_colors::{OwoColorize, Style};
+use serde::Serialize;
+
+use crate::backups::{self, Backup};
+use crate::branches::{self, BranchStatus, PushRemoteStatus};
+use crate::config::RepoConfig;
+use crate::git;
+use crate::guard::Flag;
+use crate::plan;
+use crate::submodules::{self, SubmoduleStatus};
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RepoStatus {
+    /// The config entry this status was gathered for
+    #[serde(skip)]
+    pub(crate) config: RepoConfig,
+    pub(crate) path: Utf8PathBuf,
+    /// Where the repository's refs and objects live, shared by all of its
+    /// worktrees
+    #[serde(skip)]
+    pub(crate) common_dir: Utf8PathBuf,
+    /// The main working tree, when this is a linked worktree of it
+    pub(crate) worktree_of: Option<Utf8PathBuf>,
+    /// The checked out branch, `None` when HEAD is detached
+    pub(crate) branch: Option<String>,
+    /// The remote we sync with, as configured (`origin` by default)
+    pub(crate) remote_name: String,
+    /// The URL of that remote, `None` if the repository has no such remote
+    pub(crate) remote: Option<String>,
+    /// The branch's upstream, like `origin/main`, `None` if it has none
+    pub(crate) upstream: Option<String>,
+    /// The remote the upstream is on, like `origin`, or `.` when the branch
+    /// tracks a local branch
+    #[serde(skip)]
+    pub(crate) upstream_remote: Option<String>,
+    pub(crate) has_unstaged_changes: bool,
+    pub(crate) has_staged_changes: bool,
+    /// Files with uncommitted changes, untracked ones included
+    pub(crate) changed_files: Vec<ChangedFile>,
+    /// Changed files that were picked to be left out of the commit, with
+    /// `staging = "pick"`
+    #[serde(skip)]
+    pub(crate) left_out: Vec<String>,
+    /// Number of local commits not yet on the upstream branch. Without an
+    /// upstream, the number of commits that aren't on the remote at all.
+    pub(crate) ahead: usize,
+    /// Number of upstream commits not yet in the local branch
+    pub(crate) behind: usize,
+    /// Remotes that couldn't be fetched, so their refs may be out of date
+    pub(crate) failed_fetches: Vec<FailedFetch>,
+    /// The branches listed in `branches`, except the checked out one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) branches: Vec<BranchStatus>,
+    /// The remotes listed in `push_remotes`, except `remote_name`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) push_remotes: Vec<PushRemoteStatus>,
+    /// The state of each submodule, when the config asks for it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) submodules: Vec<SubmoduleStatus>,
+    /// The backups of the repository, newest first, to prune the oldest ones
+    /// when making a new one. Of the worktrees of a repository, only the
+    /// one whose path sorts first has them, so they're pruned once.
+    #[serde(skip)]
+    pub(crate) backups: Vec<Backup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct ChangedFile {
+    /// Relative to the top of the working tree
+    pub(crate) path: String,
+    /// The two status letters `git status --porcelain` shows, like ` M` or `??`
+    pub(crate) status: String,
+    /// Reasons not to commit the file without a second look, only looked
+    /// for once a plan is about to commit it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) flags: Vec<Flag>,
+}
+
+impl ChangedFile {
+    /// Whether the index has changes to this file
+    pub(crate) fn is_staged(&self) -> bool {
+        !matches!(self.status.as_bytes()[0], b' ' | b'?')
+    }
+
+    /// Whether the working tree has changes to this file that `git add`
+    /// would stage, which includes untracked files
+    pub(crate) fn is_unstaged(&self) -> bool {
+        self.status.as_bytes()[1] != b' '
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct FailedFetch {
+    pub(crate) remote: String,
+    /// What git had to say about it, usually a single line
+    pub(crate) error: String,
+}
+
+impl RepoStatus {
+    /// The branch name, or `(detached)`, for display
+    pub(crate) fn branch_label(&self) -> &str {
+        self.branch.as_deref().unwrap_or("(detached)")
+    }
+
+    /// The normalized remote URL, or a mention that the remote is missing, for display
+    pub(crate) fn remote_label(&self) -> String {
+        match &self.remote {
+            Some(url) => crate::normalize_remote(url),
+            None => format!("no {} remote", self.remote_name)
+                .yellow()
+                .to_string(),
+        }
+    }
+
+    /// The failed fetch of the remote we sync with, if it failed. When it
+    /// did, `ahead` and `behind` were computed against stale refs.
+    pub(crate) fn stale_fetch(&self) -> Option<&FailedFetch> {
+        self.failed_fetches.iter().find(|fetch| {
+            fetch.remote == self.remote_name || Some(&fetch.remote) == self.upstream_remote.as_ref()
+        })
+    }
+
+    /// Whether there are uncommitted changes, staged or not
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.has_staged_changes || self.has_unstaged_changes
+    }
+
+    /// Whether there are uncommitted changes to files git tracks. Untracked
+    /// files alone don't get in the way of pulling.
+    pub(crate) fn has_tracked_changes(&self) -> bool {
+        self.has_staged_changes
+            || self
+                .changed_files
+                .iter()
+                .any(|file| file.is_unstaged() && file.status != "??")
+    }
+
+    pub(crate) fn has_unpushed_commits(&self) -> bool {
+        self.ahead > 0
+    }
+
+    pub(crate) fn has_unpulled_commits(&self) -> bool {
+        self.behind > 0
+    }
+
+    /// Both sides have commits the other doesn't: pulling can't fast-forward
+    pub(crate) fn has_diverged(&self) -> bool {
+        self.has_unpushed_commits() && self.has_unpulled_commits()
+    }
+
+    /// Whether a branch from `branches` or a remote from `push_remotes` is
+    /// missing commits that a plan would bring to it. Diverged ones aren't
+    /// counted: they need sorting out by hand.
+    pub(crate) fn has_other_branches_to_sync(&self) -> bool {
+        plan::fast_forwards(self).next().is_some()
+            || plan::branch_pushes(self).next().is_some()
+            || plan::remote_pushes(self, false).next().is_some()
+    }
+}
+
+/// Where a repository's git data lives, as git itself sees it
+struct RepoLayout {
+    /// The `.git` directory of this working tree
+    git_dir: Utf8PathBuf,
+    /// The `.git` directory shared by all worktrees of the repository
+    common_dir: Utf8PathBuf,
+}
+
+impl RepoLayout {
+    /// The main working tree, if this is a linked worktree
+    fn worktree_of(&self) -> Option<Utf8PathBuf> {
+        if self.git_dir == self.common_dir {
+            return None;
+        }
+        // A bare repository's worktrees have no main working tree to point at
+        match self.common_dir.file_name() {
+            Some(".git") => self.common_dir.parent().map(Utf8Path::to_owned),
+            _ => Some(self.common_dir.clone()),
+        }
+    }
+}
+
+/// Gathers the status of every repository, `jobs` at a time, sorted by path.
+///
+/// Worktrees of the same repository share their refs, so each repository is
+/// fetched once, however many of its worktrees are configured.
+///
+/// If any repository fails to report its status, all errors are printed and
+/// the process exits, since we can't come up with a sound plan anyway.
+pub(crate) async fn gather_repo_statuses(repos: &[RepoConfig], jobs: usize) -> Vec<RepoStatus> {
+    let detected = exit_on_errors(for_each_repo(repos, jobs, detect_repo).await);
+
+    let mut to_fetch: Vec<(&Utf8Path, &Utf8Path)> = Vec::new();
+    for (repo, layout) in &detected {
+        if !to_fetch.iter().any(|(dir, _)| *dir == layout.common_dir) {
+            to_fetch.push((&layout.common_dir, &repo.path));
+        }
+    }
+    let fetched = exit_on_errors(
+        for_each_repo(&to_fetch, jobs, |(common_dir, path)| async move {
+            let failed_fetches = fetch_remotes(path).await?;
+            Ok(Some((*common_dir, failed_fetches)))
+        })
+        .await,
+    );
+
+    let mut repo_statuses = exit_on_errors(
+        for_each_repo(&detected, jobs, |(repo, layout)| {
+            let (_, failed_fetches) = fetched
+                .iter()
+                .find(|(common_dir, _)| *common_dir == layout.common_dir)
+                .expect("every repository was fetched");
+            get_repo_status(repo, layout, failed_fetches.clone())
+        })
+        .await,
+    );
+
+    // Sort repo_statuses by path
+    repo_statuses.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut seen: Vec<Utf8PathBuf> = Vec::new();
+    for status in &mut repo_statuses {
+        if seen.contains(&status.common_dir) {
+            status.backups.clear();
+        } else {
+            seen.push(status.common_dir.clone());
+        }
+    }
+    repo_statuses
+}
+
+/// Runs `f` on every item, `jobs` at a time, keeping what it returns and the
+/// errors it runs into. Results come in no particular order.
+async fn for_each_repo<'a, T, R, F, Fut>(
+    items: &'a [T],
+    jobs: usize,
+    f: F,
+) -> (Vec<R>, Vec<eyre::Report>)
+where
+    F: Fn(&'a T) -> Fut,
+    Fut: Future<Output = eyre::Result<Option<R>>>,
+{
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    futures_util::stream::iter(items)
+        .map(f)
+        .buffer_unordered(jobs.max(1))
+        .for_each(|result| {
+            match result {
+                Ok(Some(result)) => results.push(result),
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+            futures_util::future::ready(())
+        })
+        .await;
+    (results, errors)
+}
+
+fn exit_on_errors<R>((results, errors): (Vec<R>, Vec<eyre::Report>)) -> Vec<R> {
+    if !errors.is_empty() {
+        eprintln!("Encountered errors:");
+        for error in errors {
+            eprintln!("  {:?}", error);
+        }
+        std::process::exit(1);
+    }
+    results
+}
+
+// RULES:
+// Things that are non-fatal (return Ok(None))
+//   - the directory does not exist
+//   - it's a bare repository, so there's no working tree to sync
+// Things that should be fatal (return an error)
+//   - the directory is not in a git repo
+//   - the directory is in a git repo, but not at the top of its working tree
+async fn detect_repo(config: &RepoConfig) -> eyre::Result<Option<(&RepoConfig, RepoLayout)>> {
+    let path = config.path.as_path();
+    if !path.exists() {
+        if config.url.is_some() {
+            eprintln!(
+                "  {} {} does not exist, run {} to clone it",
+                "⚠️".yellow(),
+                path.to_string().bright_cyan(),
+                "grit bootstrap".bright_green()
+            );
+        } else {
+            eprintln!(
+                "  {} {} does not exist",
+                "⚠️".yellow(),
+                path.to_string().bright_cyan()
+            );
+        }
+        return Ok(None);
+    }
+
+    // Asking git rather than looking for `.git` handles worktrees, where
+    // it's a file, and `GIT_DIR` setups, where it's elsewhere entirely
+    let output = git::run_git_command_quiet(
+        path,
+        &[
+            "rev-parse",
+            "--path-format=absolute",
+            "--git-dir",
+            "--git-common-dir",
+            "--is-bare-repository",
+        ],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    if !output.status.success() {
+        return Err(eyre::eyre!(
+            "{} is not a valid git repository: {}",
+            path.to_string().red(),
+            output.stderr.trim()
+        ));
+    }
+    let lines: Vec<&str> = output.stdout.lines().collect();
+    let [git_dir, common_dir, is_bare] = lines[..] else {
+        panic!(
+            "Unexpected `git rev-parse` output in {path}: {:?}",
+            output.stdout
+        );
+    };
+    if is_bare == "true" {
+        eprintln!(
+            "  {} {} is a bare repository, there's no working tree to sync",
+            "⚠️".yellow(),
+            path.to_string().bright_cyan()
+        );
+        return Ok(None);
+    }
+
+    let toplevel = git::run_git_command_quiet(
+        path,
+        &["rev-parse", "--show-toplevel"],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+    let toplevel = Utf8PathBuf::from(toplevel.stdout.trim());
+    // git resolves symlinks, so the configured path has to be resolved too
+    let resolved = path
+        .canonicalize_utf8()
+        .wrap_err_with(|| format!("Failed to resolve {}", path.to_string().red()))?;
+    if resolved != toplevel {
+        return Err(eyre::eyre!(
+            "{} is inside the git repository at {}, point the config at the latter",
+            path.to_string().red(),
+            toplevel.to_string().bright_cyan()
+        ));
+    }
+
+    Ok(Some((
+        config,
+        RepoLayout {
+            git_dir: Utf8PathBuf::from(git_dir),
+            common_dir: Utf8PathBuf::from(common_dir),
+        },
+    )))
+}
+
+// RULES:
+// Things that are recorded in the status, for the plan to deal with
+//   - a remote can't be fetched (it's unreachable, credentials are missing...)
+//   - HEAD is detached
+//   - the configured remote doesn't exist
+//   - the branch has no upstream
+// Things that should be fatal (return an error)
+//   - any of the git gathering commands fail
+//
+// Remotes are fetched beforehand, so everything is computed against fresh refs
+async fn get_repo_status(
+    config: &RepoConfig,
+    layout: &RepoLayout,
+    failed_fetches: Vec<FailedFetch>,
+) -> eyre::Result<Option<RepoStatus>> {
+    let path = config.path.as_path();
+
+    // Fails with exit code 1 when HEAD is detached
+    let branch = git::run_git_command_quiet(
+        path,
+        &["symbolic-ref", "--quiet", "--short", "HEAD"],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    let branch = match branch.status.code() {
+        Some(0) => Some(branch.stdout.trim().to_string()),
+        Some(1) => None,
+        _ => {
+            return Err(eyre::eyre!(
+                "Couldn't tell which branch is checked out in {}: {}",
+                path.to_string().red(),
+                branch.stderr.trim()
+            ));
+        }
+    };
+
+    let remote_name = config.remote_name().to_string();
+    let remote = git::run_git_command_quiet(
+        path,
+        &["remote", "get-url", &remote_name],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    let remote = remote
+        .status
+        .success()
+        .then(|| remote.stdout.trim().to_string());
+
+    let upstream = match &branch {
+        Some(_) => {
+            let upstream = git::run_git_command_quiet(
+                path,
+                &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"],
+                git::GitCommandBehavior::AllowNonZeroExitCode,
+            )
+            .await?;
+            upstream
+                .status
+                .success()
+                .then(|| upstream.stdout.trim().to_string())
+        }
+        None => None,
+    };
+    let upstream_remote = match (&upstream, &branch) {
+        (Some(_), Some(branch)) => {
+            let output = git::run_git_command_quiet(
+                path,
+                &[
+                    "for-each-ref",
+                    "--format=%(upstream:remotename)",
+                    &format!("refs/heads/{branch}"),
+                ],
+                git::GitCommandBehavior::AssertZeroExitCode,
+            )
+            .await?;
+            Some(output.stdout.trim().to_string())
+        }
+        _ => None,
+    };
+
+    let status_output = git::run_git_command_quiet(
+        path,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+
+    let changed_files = parse_porcelain(&status_output.stdout);
+
+    let staged_output = git::run_git_command_quiet(
+        path,
+        &["diff", "--cached", "--quiet"],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+
+    let (ahead, behind) = match (&upstream, &remote, &branch) {
+        (Some(_), _, _) => count_ahead_behind(path, "@{u}", "HEAD").await?,
+        // A new branch: whatever the remote doesn't have yet is ours to push
+        (None, Some(_), Some(_)) if has_commits(path).await? => {
+            let remotes = format!("--remotes={remote_name}");
+            (count_commits(path, &["HEAD", "--not", &remotes]).await?, 0)
+        }
+        _ => (0, 0),
+    };
+
+    let branches = branches::gather_branches(path, config, branch.as_deref()).await?;
+    let push_remotes =
+        branches::gather_push_remotes(path, config, branch.as_deref(), &branches).await?;
+
+    let submodules = if config.submodules() {
+        submodules::gather(path).await?
+    } else {
+        Vec::new()
+    };
+
+    let backups = backups::list(path).await?;
+
+    Ok(Some(RepoStatus {
+        config: config.clone(),
+        path: path.to_owned(),
+        common_dir: layout.common_dir.clone(),
+        worktree_of: layout.worktree_of(),
+        branch,
+        remote_name,
+        remote,
+        upstream,
+        upstream_remote,
+        has_unstaged_changes: changed_files.iter().any(ChangedFile::is_unstaged),
+        has_staged_changes: staged_output.status.code() == Some(1),
+        changed_files,
+        left_out: Vec::new(),
+        ahead,
+        behind,
+        failed_fetches,
+        branches,
+        push_remotes,
+        submodules,
+        backups,
+    }))
+}
+
+/// Parses the output of `git status --porcelain -z`: entries are separated by
+/// NUL bytes, and renames and copies are followed by an extra entry holding
+/// the original path.
+fn parse_porcelain(output: &str) -> Vec<ChangedFile> {
+    // Output is read line by line, which adds a newline after the last entry
+    let output = output.strip_suffix('\n').unwrap_or(output);
+    let mut files = Vec::new();
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+    while let Some(entry) = entries.next() {
+        let (status, path) = entry.split_at_checked(3).unwrap_or_else(|| {
+            panic!("Unexpected `git status --porcelain` entry: {entry:?}");
+        });
+        let status = status[..2].to_string();
+        if status.starts_with(['R', 'C']) {
+            entries.next();
+        }
+        files.push(ChangedFile {
+            path: path.to_string(),
+            status,
+            flags: Vec::new(),
+        });
+    }
+    files
+}
+
+/// Fetches every remote of the repository, one at a time, and returns the
+/// ones that failed. A failed fetch isn't fatal: the refs we already have
+/// are still worth looking at, as long as everyone knows they may be stale.
+async fn fetch_remotes(path: &Utf8Path) -> eyre::Result<Vec<FailedFetch>> {
+    let remotes = git::run_git_command_quiet(
+        path,
+        &["remote"],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+
+    let mut failed_fetches = Vec::new();
+    for remote in remotes
+        .stdout
+        .lines()
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+    {
+        let fetch_output = git::run_git_command_quiet(
+            path,
+            &["fetch", remote],
+            git::GitCommandBehavior::AllowNonZeroExitCode,
+        )
+        .await?;
+        if fetch_output.status.success() {
+            continue;
+        }
+
+        let error = fetch_output
+            .stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("git fetch failed without saying why")
+            .to_string();
+        eprintln!(
+            "  {} Failed to fetch {} in {}: {}",
+            "⚠️".yellow(),
+            remote.bright_yellow(),
+            crate::display_path(path).bright_cyan(),
+            error.red()
+        );
+        failed_fetches.push(FailedFetch {
+            remote: remote.to_string(),
+            error,
+        });
+    }
+    Ok(failed_fetches)
+}
+
+/// Counts the commits on each side of `upstream...local`, like `@{u}...HEAD`,
+/// as `(ahead, behind)`
+pub(crate) async fn count_ahead_behind(
+    path: &Utf8Path,
+    upstream: &str,
+    local: &str,
+) -> eyre::Result<(usize, usize)> {
+    let range = format!("{upstream}...{local}");
+    let output = git::run_git_command_quiet(
+        path,
+        &["rev-list", "--left-right", "--count", &range],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+
+    // Left is upstream-only commits, right is local-only commits
+    let counts = output.stdout.trim();
+    let parsed = counts
+        .split_once('\t')
+        .and_then(|(behind, ahead)| Some((ahead.parse().ok()?, behind.parse().ok()?)));
+    parsed.ok_or_else(|| {
+        eyre::eyre!(
+            "Couldn't parse ahead/behind counts {} in {}",
+            counts.red(),
+            path.to_string().bright_cyan()
+        )
+    })
+}
+
+/// Whether the checked out branch has any commits yet
+async fn has_commits(path: &Utf8Path) -> eyre::Result<bool> {
+    let output = git::run_git_command_quiet(
+        path,
+        &["rev-parse", "--verify", "--quiet", "HEAD"],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    Ok(output.status.success())
+}
+
+/// Counts the commits selected by revision arguments like `HEAD --not --remotes=origin`
+pub(crate) async fn count_commits(path: &Utf8Path, range: &[&str]) -> eyre::Result<usize> {
+    let mut args = vec!["rev-list", "--count"];
+    args.extend(range);
+    let output =
+        git::run_git_command_quiet(path, &args, git::GitCommandBehavior::AssertZeroExitCode)
+            .await?;
+
+    let count = output.stdout.trim();
+    count.parse().map_err(|_| {
+        eyre::eyre!(
+            "Couldn't parse commit count {} for {} in {}",
+            count.red(),
+            range.join(" ").bright_cyan(),
+            path.to_string().bright_cyan()
+        )
+    })
+}
+
+/// Prints a read-only dashboard with one row per repository.
+pub(crate) fn print_status_table(statuses: &[RepoStatus]) {
+    struct Row<'a> {
+        path: String,
+        branch: String,
+        remote: String,
+        state: String,
+        state_width: usize,
+        status: &'a RepoStatus,
+    }
+
+    let rows: Vec<Row> = statuses
+        .iter()
+        .map(|status| {
+            let (state, state_width) = describe_state(status);
+            Row {
+                path: crate::display_path(&status.path).to_string(),
+                branch: status.branch_label().to_string(),
+                remote: status.remote_label(),
+                state,
+                state_width,
+                status,
+            }
+        })
+        .collect();
+
+    let path_width = rows.iter().map(|r| r.path.len()).max().unwrap_or(0);
+    let branch_width = rows.iter().map(|r| r.branch.len()).max().unwrap_or(0);
+    let state_width = rows.iter().map(|r| r.state_width).max().unwrap_or(0);
+
+    eprintln!("\n{}", "Status:".bright_cyan());
+    for row in &rows {
+        // Pad before coloring, escape codes would throw off the alignment
+        eprintln!(
+            "  {}  {}  {}{}  {}",
+            format!("{:path_width$}", row.path).bright_cyan(),
+            format!("{:branch_width$}", row.branch).bright_green(),
+            row.state,
+            " ".repeat(state_width - row.state_width),
+            row.remote
+        );
+        for branch in &row.status.branches {
+            let (state, _) = branches::describe_branch(branch);
+            eprintln!("    {} {}  {}", "⎇".dimmed(), branch.name.green(), state);
+        }
+        for remote in &row.status.push_remotes {
+            let (state, _) = branches::describe_push_remote(remote);
+            eprintln!("    {} {}  {}", "⇡".dimmed(), remote.remote.cyan(), state);
+        }
+        for submodule in &row.status.submodules {
+            let (state, _) = submodules::describe_state(submodule);
+            eprintln!(
+                "    {} {}  {}  {}",
+                "└".dimmed(),
+                submodule.path.cyan(),
+                submodule.branch.as_deref().unwrap_or("(detached)").green(),
+                state
+            );
+        }
+    }
+
+    let dirty = statuses
+        .iter()
+        .filter(|s| {
+            s.stale_fetch().is_some()
+                || s.has_unstaged_changes
+                || s.has_staged_changes
+                || s.has_unpushed_commits()
+                || s.has_unpulled_commits()
+                || s.has_other_branches_to_sync()
+        })
+        .count();
+    eprintln!(
+        "\n{} repositories, {} need attention",
+        statuses.len().to_string().bright_yellow(),
+        dirty.to_string().bright_yellow()
+    );
+}
+
+/// Returns the colored state summary along with its visible width.
+fn describe_state(status: &RepoStatus) -> (String, usize) {
+    let mut parts = Vec::new();
+    if status.stale_fetch().is_some() {
+        parts.push(("stale".to_string(), Style::new().bright_red()));
+    }
+    if status.branch.is_none() {
+        parts.push(("detached".to_string(), Style::new().bright_red()));
+    } else if status.remote.is_some() && status.upstream.is_none() {
+        parts.push(("no upstream".to_string(), Style::new().yellow()));
+    }
+    if status.has_diverged() {
+        parts.push(("diverged".to_string(), Style::new().bright_red()));
+    }
+    if status.has_staged_changes {
+        parts.push(("staged".to_string(), Style::new().bright_yellow()));
+    }
+    if status.has_unstaged_changes {
+        parts.push(("unstaged".to_string(), Style::new().bright_red()));
+    }
+    if status.has_unpushed_commits() {
+        parts.push((format!("↑{}", status.ahead), Style::new().bright_blue()));
+    }
+    if status.has_unpulled_commits() {
+        parts.push((format!("↓{}", status.behind), Style::new().bright_magenta()));
+    }
+    if parts.is_empty() {
+        parts.push(("clean".to_string(), Style::new().green()));
+    }
+    join_state_parts(&parts)
+}
+
+/// Joins the parts of a state summary with spaces, coloring each, and
+/// returns the result along with its visible width.
+pub(crate) fn join_state_parts(parts: &[(String, Style)]) -> (String, usize) {
+    let width = parts
+        .iter()
+        .map(|(text, _)| text.chars().count())
+        .sum::<usize>()
+        + parts.len()
+        - 1;
+    let colored = parts
+        .iter()
+        .map(|(text, style)| text.style(*style).to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (colored, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_porcelain() {
+        let output = " M src/main.rs\0R  new name.txt\0old name.txt\0?? .env\0\n";
+        let files = parse_porcelain(output);
+        let paths: Vec<_> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["src/main.rs", "new name.txt", ".env"]);
+
+        let flags: Vec<_> = files
+            .iter()
+            .map(|file| (file.is_staged(), file.is_unstaged()))
+            .collect();
+        assert_eq!(flags, [(false, true), (true, false), (false, true)]);
+    }
+
+    #[test]
+    fn test_worktree_of() {
+        let layout = |git_dir: &str, common_dir: &str| RepoLayout {
+            git_dir: Utf8PathBuf::from(git_dir),
+            common_dir: Utf8PathBuf::from(common_dir),
+        };
+        // The main working tree
+        assert_eq!(layout("/repo/.git", "/repo/.git").worktree_of(), None);
+        // A linked worktree points at the main one
+        assert_eq!(
+            layout("/repo/.git/worktrees/feature", "/repo/.git").worktree_of(),
+            Some(Utf8PathBuf::from("/repo"))
+        );
+        // A bare repository has no main working tree, so it's the repository
+        assert_eq!(
+            layout("/srv/repo.git/worktrees/feature", "/srv/repo.git").worktree_of(),
+            Some(Utf8PathBuf::from("/srv/repo.git"))
+        );
+    }
+}
@@ -0,0 +1,176 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! The state of a repository's submodules, reported under it with
+//! `submodules = true`.
+//!
+//! Submodules are only looked at, never fetched nor synced: their ahead and
+//! behind counts are against whatever their remote refs were last fetched.
+
+use camino::Utf8Path;
+use owo_colors::Style;
+use serde::Serialize;
+
+use crate::git;
+use crate::status::{count_ahead_behind, join_state_parts};
+
+/// One submodule of a repository
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct SubmoduleStatus {
+    /// Relative to the top of the parent's working tree
+    pub(crate) path: String,
+    /// Whether it was cloned, with `git submodule update --init`
+    pub(crate) initialized: bool,
+    /// Whether the checked out commit isn't the one the parent records
+    pub(crate) moved: bool,
+    /// The checked out branch, `None` when HEAD is detached, as it usually is
+    pub(crate) branch: Option<String>,
+    /// Whether it has uncommitted changes, untracked files included
+    pub(crate) has_changes: bool,
+    /// Commits not on the branch's upstream yet
+    pub(crate) ahead: usize,
+    /// Commits on the branch's upstream not checked out yet
+    pub(crate) behind: usize,
+}
+
+/// Gathers the state of every submodule of the repository at `path`.
+/// Nested submodules are left out.
+pub(crate) async fn gather(path: &Utf8Path) -> eyre::Result<Vec<SubmoduleStatus>> {
+    let output = git::run_git_command_quiet(
+        path,
+        &["submodule", "status"],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+
+    let mut submodules = Vec::new();
+    for line in output.stdout.lines() {
+        let (prefix, sub_path) = parse_status_line(line);
+        let mut submodule = SubmoduleStatus {
+            path: sub_path.to_string(),
+            initialized: prefix != '-',
+            // A merge conflict on the submodule counts as moved too
+            moved: prefix == '+' || prefix == 'U',
+            branch: None,
+            has_changes: false,
+            ahead: 0,
+            behind: 0,
+        };
+        if submodule.initialized {
+            gather_working_tree(&path.join(sub_path), &mut submodule).await?;
+        }
+        submodules.push(submodule);
+    }
+    Ok(submodules)
+}
+
+async fn gather_working_tree(path: &Utf8Path, submodule: &mut SubmoduleStatus) -> eyre::Result<()> {
+    let branch = git::run_git_command_quiet(
+        path,
+        &["symbolic-ref", "--quiet", "--short", "HEAD"],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    submodule.branch = branch
+        .status
+        .success()
+        .then(|| branch.stdout.trim().to_string());
+
+    let status = git::run_git_command_quiet(
+        path,
+        &["status", "--porcelain"],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+    submodule.has_changes = !status.stdout.trim().is_empty();
+
+    if submodule.branch.is_some() {
+        let upstream = git::run_git_command_quiet(
+            path,
+            &["rev-parse", "--verify", "--quiet", "@{u}"],
+            git::GitCommandBehavior::AllowNonZeroExitCode,
+        )
+        .await?;
+        if upstream.status.success() {
+            (submodule.ahead, submodule.behind) = count_ahead_behind(path, "@{u}", "HEAD").await?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits a line of `git submodule status` into its state prefix and the
+/// submodule's path, like `+` and `vendor/lib` for
+/// `+4c2f0a9... vendor/lib (v1.2-3-g4c2f0a9)`.
+fn parse_status_line(line: &str) -> (char, &str) {
+    let mut chars = line.chars();
+    let prefix = chars.next();
+    let rest = chars.as_str();
+    let Some((prefix, (_, rest))) = prefix.zip(rest.split_once(' ')) else {
+        panic!("Unexpected `git submodule status` line: {line:?}");
+    };
+    assert!(
+        matches!(prefix, ' ' | '-' | '+' | 'U'),
+        "Unexpected `git submodule status` prefix in {line:?}"
+    );
+    // Initialized submodules are followed by what `git describe` says
+    let path = match rest.rfind(" (") {
+        Some(start) if rest.ends_with(')') => &rest[..start],
+        _ => rest,
+    };
+    (prefix, path)
+}
+
+/// Returns the colored state summary of a submodule along with its visible
+/// width, like the status table does for repositories.
+pub(crate) fn describe_state(submodule: &SubmoduleStatus) -> (String, usize) {
+    let mut parts = Vec::new();
+    if !submodule.initialized {
+        parts.push(("not initialized".to_string(), Style::new().dimmed()));
+    }
+    if submodule.moved {
+        parts.push(("moved".to_string(), Style::new().yellow()));
+    }
+    if submodule.has_changes {
+        parts.push(("changes".to_string(), Style::new().bright_red()));
+    }
+    if submodule.ahead > 0 {
+        parts.push((format!("↑{}", submodule.ahead), Style::new().bright_blue()));
+    }
+    if submodule.behind > 0 {
+        parts.push((
+            format!("↓{}", submodule.behind),
+            Style::new().bright_magenta(),
+        ));
+    }
+    if parts.is_empty() {
+        parts.push(("clean".to_string(), Style::new().green()));
+    }
+    join_state_parts(&parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(
+            parse_status_line(" 4c2f0a9e1b7d3c5a6f8e9d0c1b2a3f4e5d6c7b8a vendor/lib (v1.2)"),
+            (' ', "vendor/lib")
+        );
+        assert_eq!(
+            parse_status_line("+4c2f0a9e1b7d3c5a6f8e9d0c1b2a3f4e5d6c7b8a my docs (heads/main)"),
+            ('+', "my docs")
+        );
+        assert_eq!(
+            parse_status_line("-4c2f0a9e1b7d3c5a6f8e9d0c1b2a3f4e5d6c7b8a themes/dark"),
+            ('-', "themes/dark")
+        );
+    }
+}
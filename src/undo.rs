@@ -0,0 +1,512 @@
+// Rules:
+// 1. Always use eprintln!(), not println!()
+// 2. Be friendly with colors and emojis but not too uppity
+// 3. FIRST come up with a plan, gathering all the data, THEN apply it
+// 4. Ask for consent before applying the plan, showing the exact commands to run
+// 5. When skipping a repo, explain why (couldn't parse git-rev, etc.)
+// 6. Better to panic if git output isn't as expected than to do harmful things
+// 7. When printing specific values, like paths, numbers, keywords like "yes" and "no", use colors suited to the theme
+
+//! `grit undo`: puts HEAD and the other branches that the last pull, push or
+//! sync moved back where the journal says they were.
+//!
+//! Only local refs are put back: commits that were pushed stay on the
+//! remotes, and the next pull brings them back.
+
+use std::fmt;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use jiff::Timestamp;
+use owo_colors::OwoColorize;
+
+use crate::git::{self, GitInvocation};
+use crate::journal::{self, JournalEntry, short};
+use crate::plan::{ActionStep, BranchMove, RepoOutcome, RepoReport};
+use crate::{display_path, output};
+
+pub(crate) struct UndoPlan {
+    /// The run being taken back, `None` if there's none left in the journal
+    pub(crate) run: Option<UndoneRun>,
+    /// The repositories the run moved HEAD or a branch in
+    pub(crate) repos: Vec<RepoUndo>,
+}
+
+pub(crate) struct UndoneRun {
+    pub(crate) time: Timestamp,
+    /// The grit command, like `pull`
+    pub(crate) command: String,
+}
+
+pub(crate) struct RepoUndo {
+    pub(crate) path: Utf8PathBuf,
+    /// The checked out branch, as recorded in the journal
+    pub(crate) branch: Option<String>,
+    pub(crate) commands: Vec<GitInvocation>,
+    /// What the run did that won't be taken back, and why
+    pub(crate) notes: Vec<String>,
+    /// The other branches the commands put back, to report how they moved
+    moved_branches: Vec<String>,
+}
+
+impl UndoPlan {
+    /// Plans taking back the most recent run of the journal that wasn't
+    /// taken back yet, or the repositories of it that weren't, checking each
+    /// is still as the run left it.
+    pub(crate) async fn new(entries: Vec<JournalEntry>) -> eyre::Result<Self> {
+        let runs = journal::group_runs(entries);
+        let Some(run) = journal::last_undoable_run(&runs) else {
+            return Ok(Self {
+                run: None,
+                repos: Vec::new(),
+            });
+        };
+
+        let mut repos = Vec::new();
+        for entry in &run {
+            repos.push(RepoUndo::new(entry).await?);
+        }
+
+        Ok(Self {
+            run: Some(UndoneRun {
+                time: run[0].time,
+                command: run[0].command.clone(),
+            }),
+            repos,
+        })
+    }
+
+    pub(crate) fn is_noop(&self) -> bool {
+        self.repos.iter().all(|repo| repo.commands.is_empty())
+    }
+
+    /// Runs each repository's commands, one repository at a time: there are
+    /// only a handful of quick ones. A failing repository doesn't stop the
+    /// others, unless `fail_fast` is set.
+    pub(crate) async fn execute(&self, fail_fast: bool) -> Vec<RepoReport> {
+        let mut reports = Vec::new();
+        let mut aborted = false;
+        for repo in &self.repos {
+            if repo.commands.is_empty() {
+                let outcome = RepoOutcome::Skipped {
+                    reason: repo.notes.join("; "),
+                };
+                reports.push(RepoReport::new(repo.path.clone(), outcome));
+                continue;
+            }
+            if aborted {
+                let reason = "an earlier repository failed, and --fail-fast was passed".to_string();
+                reports.push(RepoReport::new(
+                    repo.path.clone(),
+                    RepoOutcome::Skipped { reason },
+                ));
+                continue;
+            }
+
+            let report = repo.execute().await;
+            aborted = fail_fast && matches!(report.outcome, RepoOutcome::Failed { .. });
+            reports.push(report);
+        }
+        reports
+    }
+}
+
+impl RepoUndo {
+    async fn new(entry: &JournalEntry) -> eyre::Result<Self> {
+        let mut undo = Self {
+            path: entry.path.clone(),
+            branch: entry.branch.clone(),
+            commands: Vec::new(),
+            notes: Vec::new(),
+            moved_branches: Vec::new(),
+        };
+        if !entry.path.exists() {
+            undo.notes
+                .push("the repository isn't there anymore".to_string());
+            return Ok(undo);
+        }
+
+        if entry.old_head != entry.new_head {
+            match head_blocker(entry).await? {
+                Some(note) => undo.notes.push(note),
+                None => undo.plan_head(entry),
+            }
+        }
+        for moved in &entry.branches {
+            match branch_blocker(&entry.path, moved).await? {
+                Some(note) => undo.notes.push(note),
+                None => undo.plan_branch(moved),
+            }
+        }
+
+        Ok(undo)
+    }
+
+    fn plan_head(&mut self, entry: &JournalEntry) {
+        let old = entry
+            .old_head
+            .as_deref()
+            .expect("head_blocker checked there was a commit before the run");
+        // A run that only committed leaves the committed changes staged, as
+        // they were right before; anything else goes back to the old tree
+        let mode = if entry.steps.contains(&ActionStep::Commit) {
+            "--soft"
+        } else {
+            "--keep"
+        };
+        self.commands
+            .push(GitInvocation::new(&entry.path, &["reset", mode, old]));
+
+        let pushed = entry
+            .steps
+            .iter()
+            .any(|step| matches!(step, ActionStep::Push | ActionStep::PushRemotes));
+        if pushed {
+            self.notes.push(format!(
+                "the commits the {} pushed stay on the remotes: the next pull brings them back",
+                entry.command
+            ));
+        }
+    }
+
+    fn plan_branch(&mut self, moved: &BranchMove) {
+        let (Some(old), Some(new)) = (&moved.old, &moved.new) else {
+            unreachable!("branch_blocker checked the branch moved from a commit to another");
+        };
+        // Giving the commit we expect makes git refuse if the branch moved
+        // in the meantime
+        let refname = format!("refs/heads/{}", moved.branch);
+        self.commands.push(GitInvocation::new(
+            &self.path,
+            &["update-ref", &refname, old, new],
+        ));
+        self.moved_branches.push(moved.branch.clone());
+    }
+
+    async fn execute(&self) -> RepoReport {
+        let before = self.snapshot().await;
+        let mut outcome = RepoOutcome::Succeeded;
+        for command in &self.commands {
+            if let Err(error) = command.run().await {
+                eprintln!(
+                    "{} Failed to undo in {}: {:#}",
+                    "❌".red(),
+                    display_path(&self.path).bright_cyan(),
+                    error
+                );
+                outcome = RepoOutcome::Failed {
+                    step: "undo".to_string(),
+                    error: output::strip_ansi(&format!("{error:#}")),
+                };
+                break;
+            }
+        }
+        let after = self.snapshot().await;
+
+        let branches = self
+            .moved_branches
+            .iter()
+            .zip(before.1.into_iter().zip(after.1))
+            .filter(|(_, (old, new))| old != new)
+            .map(|(branch, (old, new))| BranchMove {
+                branch: branch.clone(),
+                old,
+                new,
+            })
+            .collect();
+        RepoReport {
+            path: self.path.clone(),
+            outcome,
+            old_head: before.0,
+            new_head: after.0,
+            branches,
+            tracked_changes: Vec::new(),
+        }
+    }
+
+    /// Where HEAD and the branches to put back point, errors counting as
+    /// nothing to point to
+    async fn snapshot(&self) -> (Option<String>, Vec<Option<String>>) {
+        let head = git::resolve(&self.path, "HEAD").await.ok().flatten();
+        let mut branches = Vec::new();
+        for branch in &self.moved_branches {
+            let refname = format!("refs/heads/{branch}");
+            branches.push(git::resolve(&self.path, &refname).await.ok().flatten());
+        }
+        (head, branches)
+    }
+}
+
+/// Why HEAD can't be put back where it was before the run, if it can't:
+/// the working tree must be exactly as the run left it.
+async fn head_blocker(entry: &JournalEntry) -> eyre::Result<Option<String>> {
+    let (Some(old), Some(new)) = (&entry.old_head, &entry.new_head) else {
+        return Ok(Some(
+            "HEAD had no commit before or after the run, so there's nowhere to go back to"
+                .to_string(),
+        ));
+    };
+    let committed = entry.steps.contains(&ActionStep::Commit);
+    let pulled = entry
+        .steps
+        .iter()
+        .any(|step| matches!(step, ActionStep::Pull | ActionStep::Reconcile));
+    if committed && pulled {
+        return Ok(Some(format!(
+            "the {} committed local changes before pulling, and going back to {} would drop them from the working tree: see `git reflog`",
+            entry.command,
+            short(old)
+        )));
+    }
+
+    let path = &entry.path;
+    let branch = git::run_git_command_quiet(
+        path,
+        &["symbolic-ref", "--quiet", "--short", "HEAD"],
+        git::GitCommandBehavior::AllowNonZeroExitCode,
+    )
+    .await?;
+    let branch = branch
+        .status
+        .success()
+        .then(|| branch.stdout.trim().to_string());
+    if branch != entry.branch {
+        let recorded = entry.branch.as_deref().unwrap_or("a detached HEAD");
+        return Ok(Some(format!("{recorded} isn't checked out anymore")));
+    }
+
+    let head = git::resolve(path, "HEAD").await?;
+    if head.as_deref() != Some(new.as_str()) {
+        let now = head.as_deref().map(short).unwrap_or("nothing");
+        return Ok(Some(format!(
+            "HEAD moved since the {}, from {} to {now}",
+            entry.command,
+            short(new)
+        )));
+    }
+
+    // Untracked files, and changes the run left alone, like files kept out
+    // of the commit, don't count
+    if git::tracked_changes(path).await? != entry.tracked_changes {
+        return Ok(Some(format!(
+            "the working tree changed since the {}",
+            entry.command
+        )));
+    }
+
+    Ok(None)
+}
+
+/// Why a branch the run moved can't be put back, if it can't
+async fn branch_blocker(path: &Utf8Path, moved: &BranchMove) -> eyre::Result<Option<String>> {
+    let (Some(_), Some(new)) = (&moved.old, &moved.new) else {
+        return Ok(Some(format!(
+            "{} didn't exist before or after the run",
+            moved.branch
+        )));
+    };
+    let refname = format!("refs/heads/{}", moved.branch);
+    let worktree = git::run_git_command_quiet(
+        path,
+        &["for-each-ref", "--format=%(worktreepath)", &refname],
+        git::GitCommandBehavior::AssertZeroExitCode,
+    )
+    .await?;
+    if let Some(worktree) = worktree.stdout.lines().find(|line| !line.is_empty()) {
+        return Ok(Some(format!(
+            "{} is checked out in {} now",
+            moved.branch,
+            display_path(Utf8Path::new(worktree))
+        )));
+    }
+
+    let current = git::resolve(path, &refname).await?;
+    if current.as_deref() != Some(new.as_str()) {
+        return Ok(Some(format!("{} moved since the run", moved.branch)));
+    }
+    Ok(None)
+}
+
+impl fmt::Display for UndoPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(run) = &self.run else {
+            return writeln!(
+                f,
+                "\n{} Nothing left to undo in {}",
+                "ℹ️".bright_blue(),
+                display_path(&journal::get_journal_path()).bright_cyan()
+            );
+        };
+        writeln!(
+            f,
+            "\n{} Plan, taking back the {} of {}:",
+            "Undo".bright_cyan(),
+            run.command.bright_cyan(),
+            journal::format_time(run.time).bright_blue()
+        )?;
+
+        for repo in &self.repos {
+            writeln!(
+                f,
+                "⏪ {} {}",
+                display_path(&repo.path).bright_cyan(),
+                repo.branch
+                    .as_deref()
+                    .unwrap_or("(detached)")
+                    .bright_green()
+            )?;
+            for command in &repo.commands {
+                writeln!(f, "  {}: {}", "Will execute".bright_blue(), command)?;
+            }
+            for note in &repo.notes {
+                writeln!(f, "  {} {}", "⚠️".yellow(), note.yellow())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &Utf8Path, steps: &[ActionStep], old: &str, new: &str) -> JournalEntry {
+        JournalEntry {
+            time: "2026-10-17T07:00:00Z".parse().unwrap(),
+            host: "laptop".to_string(),
+            command: "pull".to_string(),
+            path: path.to_owned(),
+            branch: Some("main".to_string()),
+            steps: steps.to_vec(),
+            outcome: RepoOutcome::Succeeded,
+            old_head: Some(old.to_string()),
+            new_head: Some(new.to_string()),
+            branches: Vec::new(),
+            tracked_changes: Vec::new(),
+            undoes: None,
+        }
+    }
+
+    fn undo(entry: &JournalEntry) -> RepoUndo {
+        RepoUndo {
+            path: entry.path.clone(),
+            branch: entry.branch.clone(),
+            commands: Vec::new(),
+            notes: Vec::new(),
+            moved_branches: Vec::new(),
+        }
+    }
+
+    fn args(undo: &RepoUndo) -> Vec<Vec<String>> {
+        undo.commands.iter().map(|c| c.args.clone()).collect()
+    }
+
+    async fn git(path: &Utf8Path, args: &[&str]) -> eyre::Result<String> {
+        let output =
+            git::run_git_command_quiet(path, args, git::GitCommandBehavior::AssertZeroExitCode)
+                .await?;
+        Ok(output.stdout.trim().to_string())
+    }
+
+    #[test]
+    fn test_plan_head() {
+        let path = Utf8Path::new("/home/me/a");
+
+        // Pulled commits go away, the working tree with them
+        let pulled = entry(path, &[ActionStep::Pull], "1a2b3c4d", "5d6e7f8a");
+        let mut plan = undo(&pulled);
+        plan.plan_head(&pulled);
+        assert_eq!(args(&plan), vec![vec!["reset", "--keep", "1a2b3c4d"]]);
+        assert!(plan.notes.is_empty());
+
+        // Committed changes are staged again, and what was pushed stays pushed
+        let steps = [ActionStep::Stage, ActionStep::Commit, ActionStep::Push];
+        let pushed = entry(path, &steps, "1a2b3c4d", "5d6e7f8a");
+        let mut plan = undo(&pushed);
+        plan.plan_head(&pushed);
+        assert_eq!(args(&plan), vec![vec!["reset", "--soft", "1a2b3c4d"]]);
+        assert!(plan.notes[0].contains("stay on the remotes"));
+    }
+
+    #[test]
+    fn test_plan_branch() {
+        let pulled = entry(Utf8Path::new("/home/me/a"), &[], "1a2b3c4d", "1a2b3c4d");
+        let mut plan = undo(&pulled);
+        plan.plan_branch(&BranchMove {
+            branch: "release/1.0".to_string(),
+            old: Some("0a0b0c0d".to_string()),
+            new: Some("9e8f7a6b".to_string()),
+        });
+        // The commit the run left the branch at is given, so that git
+        // refuses if it moved since
+        assert_eq!(
+            args(&plan),
+            vec![vec![
+                "update-ref",
+                "refs/heads/release/1.0",
+                "0a0b0c0d",
+                "9e8f7a6b"
+            ]]
+        );
+        assert_eq!(plan.moved_branches, ["release/1.0"]);
+    }
+
+    #[tokio::test]
+    async fn test_head_blocker() -> eyre::Result<()> {
+        let root = Utf8PathBuf::try_from(std::env::temp_dir())?
+            .join(format!("grit-undo-test-{}", fastrand::u64(..)));
+        std::fs::create_dir_all(&root)?;
+        git(&root, &["init", "--quiet", "--initial-branch=main"]).await?;
+        let commit = async |message: &str| {
+            std::fs::write(root.join("notes.md"), message)?;
+            git(&root, &["add", "notes.md"]).await?;
+            let identity = ["-c", "user.name=grit", "-c", "user.email=grit@example.com"];
+            git(
+                &root,
+                &[&identity[..], &["commit", "--quiet", "-m", message]].concat(),
+            )
+            .await?;
+            git(&root, &["rev-parse", "HEAD"]).await
+        };
+        let old = commit("before").await?;
+        let new = commit("after").await?;
+
+        let pulled = entry(&root, &[ActionStep::Pull], &old, &new);
+        let clean = head_blocker(&pulled).await?;
+
+        // Going back would drop what was committed before pulling
+        let steps = [ActionStep::Stage, ActionStep::Commit, ActionStep::Reconcile];
+        let refused = head_blocker(&entry(&root, &steps, &old, &new)).await?;
+
+        let mut elsewhere = entry(&root, &[ActionStep::Pull], &old, &new);
+        elsewhere.branch = Some("dev".to_string());
+        let elsewhere = head_blocker(&elsewhere).await?;
+
+        let moved = head_blocker(&entry(&root, &[ActionStep::Pull], &old, &old)).await?;
+
+        std::fs::write(root.join("scratch.txt"), "untracked")?;
+        let untracked = head_blocker(&pulled).await?;
+
+        std::fs::write(root.join("notes.md"), "edited since")?;
+        let dirty = head_blocker(&pulled).await?;
+
+        // The run left those changes, say with --autostash
+        let mut autostashed = entry(&root, &[ActionStep::Pull], &old, &new);
+        autostashed.tracked_changes = vec![" M notes.md".to_string()];
+        let autostashed = head_blocker(&autostashed).await?;
+        std::fs::remove_dir_all(&root)?;
+
+        assert_eq!(clean, None);
+        assert!(
+            refused
+                .unwrap()
+                .contains("committed local changes before pulling")
+        );
+        assert_eq!(elsewhere.unwrap(), "dev isn't checked out anymore");
+        assert!(moved.unwrap().starts_with("HEAD moved since the pull"));
+        assert_eq!(untracked, None);
+        assert_eq!(dirty.unwrap(), "the working tree changed since the pull");
+        assert_eq!(autostashed, None);
+        Ok(())
+    }
+}